@@ -1,10 +1,31 @@
 use derive_more::From;
 
+use crate::echo360;
+
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
-    // -- Externals
+    /// A background task panicked, named by what it was doing.
+    TaskPanicked(&'static str),
+    /// Some files of a download failed, with the first error.
+    DownloadsFailed { failed: usize, first: String },
+
+    // -- Modules
     #[from]
-    Temp,
+    Echo360(echo360::Error),
 }
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::TaskPanicked(task) => write!(f, "{task} crashed"),
+            Self::DownloadsFailed { failed, first } => {
+                write!(f, "{failed} downloads failed, the first with: {first}")
+            }
+            Self::Echo360(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,22 +1,286 @@
 mod error;
-// use error::Result;
+pub use error::{Error, Result};
 
-use std::{borrow::{Borrow, BorrowMut}, cell::OnceCell, ops::DerefMut};
+use std::{
+    cell::{Cell, OnceCell},
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    ops::DerefMut,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
 
 use crate::echo360::{
     self,
-    courses::Enrollments,
-    videos::{LessonData, Video, VideoData},
+    captions::{self, CaptionFormat},
+    courses::{Enrollments, Section, Term},
+    download::{DownloadOptions, Event, ExistingFiles, Layout, Report, Tracker},
+    library::{Library, Problem},
+    session::Session,
+    sync,
+    transcripts::{self, Index},
+    videos::{Media, Quality, StreamKind, StreamRule, Video, VideoData},
     Echo360,
 };
+use crate::task::{Task, TaskState};
 use chrono::{DateTime, Local};
 use eframe::egui::{self, Context, RichText, Ui};
-use egui_extras::{Column, TableRow};
+use egui_extras::{Column, TableBody, TableRow};
+use reqwest::blocking::Client;
 
 #[derive(Default)]
 pub struct App {
     echo360: OnceCell<Echo360>,
     state: AppState,
+    hide_past_terms: bool,
+    course_search: String,
+    course_sort: CourseSort,
+    login: Task<echo360::Result<Echo360>>,
+    /// Set to close the browser of the login or re-login in progress.
+    login_cancel: Arc<AtomicBool>,
+    courses: Task<echo360::Result<Enrollments>>,
+    videos: Task<echo360::Result<Vec<VideoData>>>,
+    /// Qualities available for each lesson id, looked up once videos load.
+    renditions: Task<HashMap<String, Vec<Quality>>>,
+    reauth: Task<echo360::Result<Session>>,
+    /// Lessons downloaded to `library_path`.
+    library: Library,
+    library_path: Option<PathBuf>,
+    /// Broken files found by "Verify Library" in a folder.
+    verification: Task<(PathBuf, echo360::Result<Vec<Problem>>)>,
+    /// What the last verification found, until the files are deleted or kept.
+    verified: Option<(PathBuf, Vec<Problem>)>,
+    /// Captions found in the download directory, for searching.
+    transcripts: Task<echo360::Result<Index>>,
+    search: String,
+    /// Syllabus groups folded away, by their path of group names.
+    collapsed: HashSet<String>,
+    /// Syllabus position of the last lesson toggled, where shift-click
+    /// ranges start.
+    selection_anchor: Option<usize>,
+    /// Where to go once re-authenticated.
+    resume: AppState,
+    download: Option<Download>,
+    /// Shown in the error panel until dismissed.
+    error: Option<Error>,
+}
+
+/// A download running on a background thread.
+struct Download {
+    request: Request,
+    /// The course and settings the download started with, kept for retries.
+    section: Section,
+    options: DownloadOptions,
+    events: Receiver<Event>,
+    tracker: Tracker,
+    handle: Option<JoinHandle<echo360::Result<Report>>>,
+    /// What to run again once logged back in, when the session expired.
+    retry: Option<Request>,
+}
+
+/// What a download was asked to fetch, out of the course's syllabus.
+#[derive(Clone)]
+enum Request {
+    /// The lessons of this syllabus marked for download.
+    Lessons(Vec<Video>),
+    /// The lessons of this syllabus that earlier syncs to the download path
+    /// haven't fetched, recorded in its manifest.
+    Sync(Vec<Video>),
+}
+
+impl Request {
+    fn run(
+        &self,
+        client: &Client,
+        domain: String,
+        section: &Section,
+        options: &DownloadOptions,
+        events: Sender<Event>,
+    ) -> echo360::Result<Report> {
+        match self {
+            Self::Lessons(syllabus) => {
+                let syllabus: Vec<&Video> = syllabus.iter().collect();
+                let videos: Vec<&Video> = syllabus
+                    .iter()
+                    .copied()
+                    .filter(|video| video.download)
+                    .collect();
+                Video::download_videos(
+                    client,
+                    domain,
+                    section,
+                    &syllabus,
+                    &videos,
+                    options,
+                    Some(events),
+                )
+            }
+            Self::Sync(videos) => {
+                let videos: Vec<&Video> = videos.iter().collect();
+                sync::sync(
+                    &options.path,
+                    &section.section_id,
+                    &videos,
+                    |_| true,
+                    |pending| {
+                        Video::download_videos(
+                            client,
+                            domain,
+                            section,
+                            &videos,
+                            pending,
+                            options,
+                            Some(events),
+                        )
+                    },
+                )
+            }
+        }
+    }
+
+    /// The part of the request covering `lessons`.
+    fn only(&self, lessons: &HashSet<String>) -> Self {
+        match self {
+            Self::Lessons(syllabus) => Self::Lessons(
+                syllabus
+                    .iter()
+                    .cloned()
+                    .map(|mut video| {
+                        video.download &= lessons.contains(&video.lesson.id);
+                        video
+                    })
+                    .collect(),
+            ),
+            // The manifest already knows which lessons made it
+            Self::Sync(videos) => Self::Sync(videos.clone()),
+        }
+    }
+}
+
+impl Download {
+    /// Plans and runs the download on a background thread, as planning asks
+    /// the server which renditions exist.
+    fn start(echo360: &Echo360, request: Request) -> echo360::Result<Self> {
+        let options = echo360.download_options()?;
+        let section = echo360.selected.borrow().clone();
+        Ok(Self::spawn(echo360, request, section, options))
+    }
+
+    /// Runs what an expired session interrupted, into the course and with the
+    /// settings the download started with, whatever is selected now.
+    fn restart(&mut self, echo360: &Echo360) -> Option<Self> {
+        let request = self.retry.take()?;
+        Some(Self::spawn(
+            echo360,
+            request,
+            self.section.clone(),
+            self.options.clone(),
+        ))
+    }
+
+    fn spawn(
+        echo360: &Echo360,
+        request: Request,
+        section: Section,
+        options: DownloadOptions,
+    ) -> Self {
+        let client = echo360.client.clone();
+        let domain = echo360.domain.clone();
+
+        let (sender, events) = mpsc::channel();
+        let work = request.clone();
+        let (work_section, work_options) = (section.clone(), options.clone());
+        let handle =
+            thread::spawn(move || work.run(&client, domain, &work_section, &work_options, sender));
+
+        Self {
+            request,
+            section,
+            options,
+            events,
+            tracker: Tracker::default(),
+            handle: Some(handle),
+            retry: None,
+        }
+    }
+
+    /// Applies pending progress events, returns whether the download is still
+    /// running, or what went wrong once it has finished.
+    fn poll(&mut self) -> Result<bool> {
+        for event in self.events.try_iter() {
+            self.tracker.update(event);
+        }
+
+        if self.handle.as_ref().is_some_and(JoinHandle::is_finished) {
+            let report = self
+                .handle
+                .take()
+                .unwrap()
+                .join()
+                .map_err(|_| Error::TaskPanicked("download"))?;
+            let report = match report {
+                Err(echo360::Error::SessionExpired) => {
+                    self.retry = Some(self.request.clone());
+                    return Err(echo360::Error::SessionExpired.into());
+                }
+                report => report?,
+            };
+            let expired = report.expired_lessons();
+            if !expired.is_empty() {
+                self.retry = Some(self.request.only(&expired));
+                return Err(echo360::Error::SessionExpired.into());
+            }
+            log::info!(
+                "{} of {} files downloaded",
+                report.succeeded(),
+                report.outcomes.len()
+            );
+            let first = report
+                .failed()
+                .find_map(|outcome| outcome.result.as_ref().err());
+            if let Some(err) = first {
+                return Err(Error::DownloadsFailed {
+                    failed: report.outcomes.len() - report.succeeded(),
+                    first: err.to_string(),
+                });
+            }
+        }
+        Ok(self.handle.is_some())
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+enum CourseSort {
+    #[default]
+    Code,
+    Name,
+    Lessons,
+}
+
+impl CourseSort {
+    const ALL: [Self; 3] = [Self::Code, Self::Name, Self::Lessons];
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Code => "Course code",
+            Self::Name => "Course name",
+            Self::Lessons => "Most lessons",
+        }
+    }
+
+    fn sort(self, sections: &mut [&Section]) {
+        match self {
+            Self::Code => sections.sort_by(|a, b| a.course_code.cmp(&b.course_code)),
+            Self::Name => sections.sort_by_key(|section| section.course_name.to_lowercase()),
+            Self::Lessons => sections.sort_by_key(|section| Reverse(section.lesson_count)),
+        }
+    }
 }
 
 #[derive(Default)]
@@ -27,11 +291,36 @@ enum AppState {
     SelectingCourse,
     LoadingVideos,
     SelectingVideos,
+    SearchingTranscripts,
+    Reauthenticating,
 }
 
 impl App {
     fn login_screen(&mut self, ctx: &Context) {
-        let mut login_button = |echo360: &mut OnceCell<Echo360>, ui: &mut Ui| {
+        match self.login.try_state() {
+            Ok(TaskState::NotFired) => {}
+            Ok(TaskState::Loading(_)) => {
+                if loading_screen(ctx, "Log in through the browser window") {
+                    self.cancel_login();
+                    self.login = Task::default();
+                }
+                return;
+            }
+            Ok(TaskState::Ok(_)) => match self.login.take().unwrap() {
+                Ok(echo360) => {
+                    self.echo360 = OnceCell::from(echo360);
+                    self.state = AppState::LoadingCourses;
+                    return;
+                }
+                Err(err) => self.error = failed(err),
+            },
+            Err(()) => {
+                self.error = failed(Error::TaskPanicked("login"));
+                self.login = Task::default();
+            }
+        }
+
+        let login_button = |app: &mut Self, ui: &mut Ui| {
             if ui
                 .add(egui::Button::new(
                     RichText::new("Log in to see Courses")
@@ -41,22 +330,33 @@ impl App {
                 ))
                 .clicked()
             {
-                let _ = echo360.set(Echo360::login().unwrap());
-                ui.add(egui::Spinner::new().size(39.0));
-                self.state = AppState::LoadingCourses;
+                let cancel = app.new_login();
+                app.login.fire(move || Echo360::login(&cancel));
             };
         };
 
         egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.add_space(10.0);
-                login_button(&mut self.echo360, ui);
+                login_button(self, ui);
                 ui.add_space(10.0);
             });
         });
     }
 
     fn course_select_screen(&mut self, ctx: &Context) {
+        let echo360 = self.echo360.get().unwrap();
+        let Some(enrollments) = echo360.enrollments.get() else {
+            return;
+        };
+        if enrollments.user_sections.is_empty() {
+            if no_courses_screen(ctx, &enrollments.raw) {
+                self.state = AppState::LoadingCourses;
+            }
+            return;
+        }
+        let groups = enrollments.by_term();
+
         egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.add_space(10.0);
@@ -68,91 +368,483 @@ impl App {
                 );
                 ui.add_space(10.0);
             });
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.course_search).hint_text("Search courses"),
+                );
+                ui.add_space(20.);
+                egui::ComboBox::from_id_source("Course Sort")
+                    .selected_text(self.course_sort.label())
+                    .show_ui(ui, |ui| {
+                        for sort in CourseSort::ALL {
+                            ui.selectable_value(&mut self.course_sort, sort, sort.label());
+                        }
+                    });
+                ui.label("Sort");
+                if groups
+                    .iter()
+                    .any(|(term, _)| term.is_some_and(Term::is_past))
+                {
+                    ui.add_space(20.);
+                    ui.add(toggle(&mut self.hide_past_terms));
+                    ui.label("Hide past terms");
+                }
+            });
+            ui.add_space(5.);
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
-            let echo360 = self.echo360.get().unwrap();
-
             egui::ScrollArea::vertical()
                 .auto_shrink(false)
                 .show(ui, |ui| {
-                    ui.columns(3, |columns| {
-                        if let Some(enrollments) = echo360.enrollments.get() {
-                            for (i, enrollment) in enrollments.user_sections.iter().enumerate() {
+                    for (term, mut sections) in groups {
+                        if self.hide_past_terms && term.is_some_and(Term::is_past) {
+                            continue;
+                        }
+                        sections.retain(|section| section.matches(&self.course_search));
+                        if sections.is_empty() {
+                            continue;
+                        }
+                        self.course_sort.sort(&mut sections);
+
+                        ui.add_space(10.);
+                        ui.heading(term.map_or("Other", |term| term.name.as_str()));
+                        ui.separator();
+                        ui.columns(3, |columns| {
+                            for (i, enrollment) in sections.into_iter().enumerate() {
                                 columns[i % 3].vertical_centered(|ui| {
-                                    if ui
-                                        .add(
-                                            egui::Button::new(&enrollment.section_name)
-                                                .min_size([200., 50.].into()),
-                                        )
-                                        .clicked()
-                                    {
+                                    if course_card(ui, enrollment).clicked() {
                                         // Select class
                                         echo360.selected.replace(enrollment.clone());
+                                        self.collapsed.clear();
+                                        self.selection_anchor = None;
                                         self.state = AppState::LoadingVideos;
                                     }
                                 });
                             }
-                        }
-                    });
+                        });
+                    }
                 });
         });
     }
 
     fn load_courses(&mut self, ctx: &Context) {
+        let echo360 = self.echo360.get().unwrap();
+
+        match self.courses.try_state() {
+            Ok(TaskState::NotFired) => {
+                let client = echo360.client.clone();
+                let domain = echo360.domain.clone();
+                self.courses.fire(move || Enrollments::get(&client, domain));
+            }
+            Ok(TaskState::Loading(_)) => {}
+            Ok(TaskState::Ok(_)) => {
+                match self.courses.take().unwrap() {
+                    Ok(enrollments) => {
+                        // Replaces the courses on a reload
+                        let echo360 = self.echo360.get_mut().unwrap();
+                        echo360.enrollments.take();
+                        let _ = echo360.enrollments.set(enrollments);
+                        self.state = AppState::SelectingCourse;
+                    }
+                    Err(echo360::Error::SessionExpired) => {
+                        self.resume = AppState::LoadingCourses;
+                        self.state = AppState::Reauthenticating;
+                    }
+                    Err(err) => {
+                        self.error = failed(err);
+                        self.state = AppState::LoggingIn;
+                    }
+                }
+                return;
+            }
+            Err(()) => {
+                self.error = failed(Error::TaskPanicked("loading courses"));
+                self.courses = Task::default();
+                self.state = AppState::LoggingIn;
+                return;
+            }
+        }
+
+        if loading_screen(ctx, "Loading Courses") {
+            self.courses = Task::default();
+            self.state = AppState::LoggingIn;
+        }
+    }
+
+    fn load_videos(&mut self, ctx: &Context) {
+        let echo360 = self.echo360.get().unwrap();
+
+        match self.videos.try_state() {
+            Ok(TaskState::NotFired) => {
+                let client = echo360.client.clone();
+                let domain = echo360.domain.clone();
+                let section_id = echo360.selected.borrow().section_id.clone();
+                self.videos
+                    .fire(move || Video::get_videos(&client, domain, &section_id));
+            }
+            Ok(TaskState::Loading(_)) => {}
+            Ok(TaskState::Ok(_)) => {
+                match self.videos.take().unwrap() {
+                    Ok(videos) => {
+                        self.renditions = Task::default();
+                        self.renditions.fire(find_renditions(echo360, &videos));
+                        echo360.videos.replace(videos);
+                        self.state = AppState::SelectingVideos;
+                    }
+                    Err(echo360::Error::SessionExpired) => {
+                        self.resume = AppState::LoadingVideos;
+                        self.state = AppState::Reauthenticating;
+                    }
+                    Err(err) => {
+                        self.error = failed(err);
+                        self.state = AppState::SelectingCourse;
+                    }
+                }
+                return;
+            }
+            Err(()) => {
+                self.error = failed(Error::TaskPanicked("loading videos"));
+                self.videos = Task::default();
+                self.state = AppState::SelectingCourse;
+                return;
+            }
+        }
+
+        if loading_screen(ctx, "Loading Videos") {
+            self.videos = Task::default();
+            self.state = AppState::SelectingCourse;
+        }
+    }
+
+    /// Logs in again after a request found the session expired, then retries it.
+    fn reauth_screen(&mut self, ctx: &Context) {
+        match self.reauth.try_state() {
+            Ok(TaskState::NotFired) => {
+                let cancel = self.new_login();
+                self.reauth.fire(move || Echo360::browser_session(&cancel));
+            }
+            Ok(TaskState::Loading(_)) => {}
+            Ok(TaskState::Ok(_)) => {
+                let result = self
+                    .reauth
+                    .take()
+                    .unwrap()
+                    .and_then(|session| self.echo360.get_mut().unwrap().use_session(&session));
+                match result {
+                    Ok(()) => self.state = std::mem::take(&mut self.resume),
+                    Err(err) => {
+                        self.error = failed(err);
+                        self.cancel_reauth();
+                    }
+                }
+                return;
+            }
+            Err(()) => {
+                self.error = failed(Error::TaskPanicked("login"));
+                self.reauth = Task::default();
+                self.cancel_reauth();
+                return;
+            }
+        }
+
+        if loading_screen(ctx, "Session expired, log in through the browser window") {
+            self.cancel_login();
+            self.reauth = Task::default();
+            self.cancel_reauth();
+        }
+    }
+
+    /// Searches the captions in the download directory and plays recordings
+    /// from a matching cue.
+    fn search_screen(&mut self, ctx: &Context) {
+        let echo360 = self.echo360.get().unwrap();
+
         egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.add_space(10.0);
-                ui.label(
-                    RichText::new("Loading Courses")
-                        .size(32.0)
-                        .heading()
-                        .strong(),
+            ui.horizontal_centered(|ui| {
+                if ui.button("Back").clicked() {
+                    self.state = AppState::SelectingVideos;
+                }
+                if ui.button("Rebuild Index").clicked() {
+                    self.transcripts = Task::default();
+                }
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.search)
+                        .hint_text("Search transcripts")
+                        .desired_width(f32::INFINITY),
                 );
-                ui.add_space(10.0);
             });
         });
 
-        let echo360 = self.echo360.get().unwrap();
+        let index = match self.transcripts.try_state() {
+            Ok(TaskState::NotFired) => {
+                let dir = PathBuf::from(echo360.download_path.borrow().as_str());
+                self.transcripts.fire(move || Index::build(&dir));
+                return;
+            }
+            Ok(TaskState::Loading(_)) => {
+                ctx.request_repaint_after(Duration::from_millis(100));
+                return;
+            }
+            Ok(TaskState::Ok(Ok(index))) => index,
+            Ok(TaskState::Ok(Err(_))) => {
+                let err = self.transcripts.take().unwrap().unwrap_err();
+                self.error = failed(err);
+                self.state = AppState::SelectingVideos;
+                return;
+            }
+            Err(()) => {
+                self.error = failed(Error::TaskPanicked("indexing transcripts"));
+                self.transcripts = Task::default();
+                self.state = AppState::SelectingVideos;
+                return;
+            }
+        };
+
+        let hits = index.search(&self.search);
+        let mut play: Option<(PathBuf, Duration)> = None;
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.label(format!(
+                "{} matches in {} transcripts",
+                hits.len(),
+                index.transcripts.len()
+            ));
+            egui_extras::TableBuilder::new(ui)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::remainder())
+                .column(Column::auto())
+                .striped(true)
+                .body(|body| {
+                    body.rows(30.0, hits.len(), |index, mut row| {
+                        let hit = &hits[index];
+                        row.col(|ui| {
+                            ui.label(&hit.transcript.lesson)
+                                .on_hover_text(hit.transcript.path.display().to_string());
+                        });
+                        row.col(|ui| {
+                            ui.label(captions::clock(hit.cue.start, '.'));
+                        });
+                        row.col(|ui| {
+                            ui.label(hit.cue.text.replace('\n', " "));
+                        });
+                        row.col(|ui| {
+                            let video = hit.transcript.video.as_ref();
+                            if ui
+                                .add_enabled(video.is_some(), egui::Button::new("Play"))
+                                .clicked()
+                            {
+                                play = video.map(|video| (video.clone(), hit.cue.start));
+                            }
+                        });
+                    });
+                });
+        });
 
-        echo360
-            .enrollments
-            .set(Enrollments::get(&echo360.client, &echo360.domain).unwrap())
-            .unwrap();
-        self.state = AppState::SelectingCourse;
+        if let Some((video, offset)) = play {
+            if let Err(err) = transcripts::play(&video, offset) {
+                self.error = failed(err);
+            }
+        }
     }
 
-    fn load_videos(&mut self, ctx: &Context) {
-        egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
-            ui.vertical_centered(|ui| {
-                ui.add_space(10.0);
-                ui.label(
-                    RichText::new("Loading Videos")
-                        .size(32.0)
-                        .heading()
-                        .strong(),
-                );
-                ui.add_space(10.0);
+    /// Shows what "Verify Library" found, and deletes the broken files once
+    /// asked to, returning their lessons to download again.
+    fn verified_panel(&mut self, ctx: &Context, downloading: bool) -> Vec<String> {
+        let Some((_, problems)) = &self.verified else {
+            return Vec::new();
+        };
+        let (mut delete, mut keep) = (false, false);
+
+        egui::TopBottomPanel::top("Verified Panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if problems.is_empty() {
+                    ui.label("All downloaded files are intact");
+                } else {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        format!("{} broken files", problems.len()),
+                    );
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if problems.is_empty() {
+                        keep = ui.button("Dismiss").clicked();
+                        return;
+                    }
+                    keep = ui.button("Keep").clicked();
+                    delete = ui
+                        .add_enabled(!downloading, egui::Button::new("Delete and Select"))
+                        .on_hover_text(
+                            "Delete the broken files and select their lessons to download again",
+                        )
+                        .clicked();
+                });
             });
+            egui::ScrollArea::vertical()
+                .max_height(120.0)
+                .show(ui, |ui| {
+                    for problem in problems {
+                        ui.label(format!("{}: {}", problem.path.display(), problem.reason));
+                    }
+                });
         });
 
-        let echo360 = self.echo360.get().unwrap();
+        if keep {
+            self.verified = None;
+        }
+        if !delete {
+            return Vec::new();
+        }
+        let (dir, problems) = self.verified.take().unwrap();
+        match Library::repair(&dir, &problems) {
+            Ok(()) => {
+                self.library_path = None;
+                problems
+                    .into_iter()
+                    .map(|problem| problem.lesson_id)
+                    .collect()
+            }
+            Err(err) => {
+                self.error = failed(err);
+                Vec::new()
+            }
+        }
+    }
 
-        echo360.videos.replace(
-            Video::get_videos(
-                &echo360.client,
-                &echo360.domain,
-                &echo360.selected.borrow().section_id,
-            )
-            .unwrap(),
-        );
-        self.state = AppState::SelectingVideos;
+    fn error_panel(&mut self, ctx: &Context) {
+        let Some(message) = self.error.as_ref().map(Error::to_string) else {
+            return;
+        };
+
+        egui::TopBottomPanel::top("Error Panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.colored_label(ui.visuals().error_fg_color, message);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("Dismiss").clicked() {
+                        self.error = None;
+                    }
+                });
+            });
+        });
+    }
+
+    /// A fresh cancellation flag for a browser login about to start.
+    fn new_login(&mut self) -> Arc<AtomicBool> {
+        self.login_cancel = Arc::default();
+        self.login_cancel.clone()
+    }
+
+    /// Closes the browser of the login in progress, if any.
+    fn cancel_login(&self) {
+        self.login_cancel.store(true, Ordering::Relaxed);
+    }
+
+    fn cancel_reauth(&mut self) {
+        self.state = match std::mem::take(&mut self.resume) {
+            AppState::LoadingVideos => AppState::SelectingCourse,
+            AppState::SelectingVideos => {
+                // Give up on the interrupted download
+                if let Some(download) = &mut self.download {
+                    download.retry = None;
+                }
+                self.error = failed(echo360::Error::SessionExpired);
+                AppState::SelectingVideos
+            }
+            _ => AppState::LoggingIn,
+        };
     }
 
     fn video_select_screen(&mut self, ctx: &Context) {
+        // Start again what an expired session interrupted, now logged back in
+        let echo360 = self.echo360.get().unwrap();
+        if let Some(download) = self
+            .download
+            .as_mut()
+            .and_then(|download| download.restart(echo360))
+        {
+            self.download = Some(download);
+        }
+        let was_downloading = self
+            .download
+            .as_ref()
+            .is_some_and(|download| download.handle.is_some());
+        let downloading = match self.download.as_mut().map(Download::poll) {
+            Some(Ok(running)) => running,
+            Some(Err(Error::Echo360(echo360::Error::SessionExpired))) => {
+                // Pick up whatever finished before the session expired
+                self.library_path = None;
+                self.resume = AppState::SelectingVideos;
+                self.state = AppState::Reauthenticating;
+                return;
+            }
+            Some(Err(err)) => {
+                self.error = failed(err);
+                false
+            }
+            None => false,
+        };
+
+        let verifying = matches!(self.verification.try_state(), Ok(TaskState::Loading(_)));
+        match self.verification.try_state() {
+            Ok(TaskState::Ok(_)) => match self.verification.take().unwrap() {
+                (dir, Ok(problems)) => {
+                    for problem in &problems {
+                        log::warn!("{}: {}", problem.path.display(), problem.reason);
+                    }
+                    log::info!("verified library, {} broken files", problems.len());
+                    self.verified = Some((dir, problems));
+                }
+                (_, Err(err)) => self.error = failed(err),
+            },
+            Err(()) => {
+                self.error = failed(Error::TaskPanicked("verifying the library"));
+                self.verification = Task::default();
+            }
+            _ => {}
+        }
+        let broken = self.verified_panel(ctx, downloading);
+        if verifying {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
+        let renditions = match self.renditions.try_state() {
+            Ok(TaskState::Ok(renditions)) => Some(renditions),
+            Ok(TaskState::Loading(_)) => {
+                ctx.request_repaint_after(Duration::from_millis(100));
+                None
+            }
+            _ => None,
+        };
+
         let echo360 = self.echo360.get().unwrap();
         let mut videos = echo360.videos.borrow_mut();
-        let mut captions = echo360.captions.borrow_mut();
+
+        // Reload what's been downloaded when the path changes or a download ends
+        let path = PathBuf::from(echo360.download_path.borrow().as_str());
+        if was_downloading && !downloading {
+            self.library_path = None;
+        }
+        if self.library_path.as_ref() != Some(&path) {
+            self.library = Library::load(&path).unwrap_or_else(|err| {
+                self.error = failed(err);
+                Library::default()
+            });
+            for video in VideoData::flatten_mut(&mut videos) {
+                if self.library.is_downloaded(&video.lesson.id) {
+                    video.download = false;
+                }
+                // Broken files were deleted, queue them again
+                if broken.contains(&video.lesson.id) {
+                    video.download = true;
+                }
+            }
+            self.library_path = Some(path.clone());
+        }
+        let library = &self.library;
+
+        if downloading {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
 
         egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
             ui.horizontal_centered(|ui| {
@@ -160,8 +852,33 @@ impl App {
                     self.state = AppState::SelectingCourse;
                     return;
                 };
-                ui.centered_and_justified(|ui| {
-                    ui.label(RichText::new("Choose Videos").size(32.0).heading().strong());
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let label = if verifying {
+                        "Verifying..."
+                    } else {
+                        "Verify Library"
+                    };
+                    if ui
+                        .add_enabled(!verifying && !downloading, egui::Button::new(label))
+                        .on_hover_text("Re-check downloaded files and list any broken ones")
+                        .clicked()
+                    {
+                        let path = path.clone();
+                        self.verified = None;
+                        self.verification = Task::default();
+                        self.verification.fire(move || {
+                            let problems =
+                                Library::load(&path).map(|library| library.verify(&path));
+                            (path, problems)
+                        });
+                    }
+                    if ui.button("Search Transcripts").clicked() {
+                        self.transcripts = Task::default();
+                        self.state = AppState::SearchingTranscripts;
+                    }
+                    ui.centered_and_justified(|ui| {
+                        ui.label(RichText::new("Choose Videos").size(32.0).heading().strong());
+                    });
                 });
             });
         });
@@ -179,45 +896,200 @@ impl App {
                             .borrow_mut()
                             .deref_mut(),
                     );
+                    ui.label("File names:");
+                    ui.add(
+                        egui::TextEdit::singleline(echo360.template.borrow_mut().deref_mut())
+                            .desired_width(180.),
+                    )
+                    .on_hover_text(TEMPLATE_HELP);
 
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
                         ui.add_space(20.);
-                        if ui.button("Download Videos").clicked() {
-                            let mut downloads: Vec<&Video> = Vec::new();
-                            // Get selected videos
-                            for video_data in videos.iter() {
-                                if let VideoData::SyllabusLessonType { lesson } = video_data {
-                                    if lesson.download {
-                                        downloads.push(&lesson);
-                                    }
-                                }
+                        if ui
+                            .add_enabled(!downloading, egui::Button::new("Sync Course"))
+                            .on_hover_text("Download lessons not fetched to this path before")
+                            .clicked()
+                        {
+                            let syllabus = VideoData::flatten(&videos).into_iter().cloned();
+                            match Download::start(echo360, Request::Sync(syllabus.collect())) {
+                                Ok(download) => self.download = Some(download),
+                                Err(err) => self.error = failed(err),
                             }
+                        }
+                        if ui
+                            .add_enabled(!downloading, egui::Button::new("Download Videos"))
+                            .clicked()
+                        {
+                            // The whole syllabus, as names are chosen over
+                            // it, with the selected lessons marked
+                            let syllabus: Vec<Video> =
+                                VideoData::flatten(&videos).into_iter().cloned().collect();
 
-                            // Download the videos
-                            Video::download_videos(
-                                &echo360.client,
-                                &echo360.domain,
-                                &echo360.selected.borrow().course_code,
-                                &downloads,
-                                echo360.download_path.borrow().to_string(),
-                                *captions,
-                            );
+                            // Download the videos in the background
+                            match Download::start(echo360, Request::Lessons(syllabus)) {
+                                Ok(download) => self.download = Some(download),
+                                Err(err) => self.error = failed(err),
+                            }
                         };
                         ui.add_space(20.);
-                        ui.add(toggle(&mut captions));
+                        ui.add(
+                            egui::DragValue::new(echo360.workers.borrow_mut().deref_mut())
+                                .clamp_range(1..=16),
+                        );
+                        ui.label("Parallel downloads");
+                        ui.add_space(20.);
+                        let mut existing = echo360.existing.borrow_mut();
+                        egui::ComboBox::from_id_source("Existing Files")
+                            .selected_text(existing.label())
+                            .show_ui(ui, |ui| {
+                                for policy in ExistingFiles::ALL {
+                                    ui.selectable_value(&mut *existing, policy, policy.label());
+                                }
+                            });
+                        ui.label("Existing files");
+                        ui.add_space(20.);
+                        let mut quality = echo360.quality.borrow_mut();
+                        egui::ComboBox::from_id_source("Quality")
+                            .selected_text(quality.label())
+                            .show_ui(ui, |ui| {
+                                for option in Quality::ALL {
+                                    ui.selectable_value(&mut *quality, option, option.label());
+                                }
+                            });
+                        ui.label("Quality");
+                        ui.add_space(20.);
+                        let mut streams = echo360.streams.borrow_mut();
+                        egui::ComboBox::from_id_source("Streams")
+                            .selected_text(streams.label())
+                            .show_ui(ui, |ui| {
+                                for rule in StreamRule::ALL {
+                                    ui.selectable_value(&mut *streams, rule, rule.label());
+                                }
+                            });
+                        ui.label("Streams");
+                        ui.add_space(20.);
+                        let mut layout = echo360.layout.borrow_mut();
+                        egui::ComboBox::from_id_source("Layout")
+                            .selected_text(layout.label())
+                            .show_ui(ui, |ui| {
+                                for option in Layout::ALL {
+                                    ui.selectable_value(&mut *layout, option, option.label());
+                                }
+                            });
+                        ui.label("Layout");
+                        ui.add_space(20.);
+                        ui.add(toggle(echo360.captions.borrow_mut().deref_mut()));
                         ui.label("Download Captions");
+                        let mut formats = echo360.caption_formats.borrow_mut();
+                        let selected = match formats.len() {
+                            0 => "No formats".to_owned(),
+                            _ => formats
+                                .iter()
+                                .map(|format| format.label())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        };
+                        ui.add_enabled_ui(*echo360.captions.borrow(), |ui| {
+                            ui.menu_button(selected, |ui| {
+                                for format in CaptionFormat::ALL {
+                                    let mut on = formats.contains(&format);
+                                    if ui.checkbox(&mut on, format.label()).changed() {
+                                        if on {
+                                            formats.push(format);
+                                        } else {
+                                            formats.retain(|other| *other != format);
+                                        }
+                                    }
+                                }
+                            });
+                        });
                     });
                 });
             });
 
-        let entry = |mut row: TableRow, lesson: &mut Video| {
-            let show = lesson.has_content.clone();
+        let preferred = *echo360.quality.borrow();
+        let rule = *echo360.streams.borrow();
+        egui::TopBottomPanel::bottom("Selection Panel").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut lessons: Vec<&mut Video> = VideoData::flatten_mut(&mut videos)
+                    .into_iter()
+                    .filter(|lesson| lesson.has_content)
+                    .collect();
+                if ui.button("Select All").clicked() {
+                    lessons.iter_mut().for_each(|lesson| lesson.download = true);
+                }
+                if ui.button("Select None").clicked() {
+                    lessons
+                        .iter_mut()
+                        .for_each(|lesson| lesson.download = false);
+                }
+                if ui.button("Invert").clicked() {
+                    lessons
+                        .iter_mut()
+                        .for_each(|lesson| lesson.download = !lesson.download);
+                }
+                if ui.button("Select Not Downloaded").clicked() {
+                    for lesson in &mut lessons {
+                        lesson.download = !library.is_downloaded(&lesson.lesson.id);
+                    }
+                }
+
+                let selected: Vec<&Video> = lessons
+                    .iter()
+                    .map(|lesson| &**lesson)
+                    .filter(|lesson| lesson.download)
+                    .collect();
+                let size: u64 = selected
+                    .iter()
+                    .filter_map(|lesson| {
+                        let available = renditions.and_then(|map| map.get(&lesson.lesson.id));
+                        estimated_size(lesson, available, preferred, rule)
+                    })
+                    .sum();
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    ui.label(format!(
+                        "{} selected, about {}",
+                        selected.len(),
+                        format_bytes(size)
+                    ))
+                    .on_hover_text("Estimated from the length of each recording");
+                });
+            });
+        });
+
+        if let Some(download) = &self.download {
+            egui::TopBottomPanel::bottom("Progress Panel").show(ctx, |ui| {
+                let (fraction, speed, eta) = download.tracker.overall();
+                let planning = downloading && download.tracker.jobs.is_empty();
+                let fraction = if planning { 0. } else { fraction };
+                let text = match eta {
+                    _ if planning => "Preparing downloads".to_owned(),
+                    Some(eta) if downloading => format!(
+                        "{:.0}%  {}/s  {} left",
+                        fraction * 100.,
+                        format_bytes(speed as u64),
+                        format_duration(eta)
+                    ),
+                    _ => format!("{:.0}%", fraction * 100.),
+                };
+                ui.add_space(5.);
+                ui.add(egui::ProgressBar::new(fraction).text(text));
+                ui.add_space(5.);
+            });
+        }
+
+        let tracker = self.download.as_ref().map(|download| &download.tracker);
+        // The lesson toggled this frame, and whether it was switched on
+        let toggled: Cell<Option<(usize, bool)>> = Cell::new(None);
+        let entry = |mut row: TableRow, lesson: &mut Video, depth: usize| {
+            let show = lesson.has_content;
             let name = lesson.lesson.display_name.clone();
             let start = lesson.start_time_utc.as_ref();
             let end = lesson.end_time_utc.as_ref();
 
             row.col(|ui| {
                 ui.horizontal_centered(|ui| {
+                    ui.add_space(depth as f32 * INDENT);
                     ui.add_enabled_ui(show, |ui| {
                         ui.label(name);
                     });
@@ -243,11 +1115,82 @@ impl App {
                     }
                 };
             });
+            row.col(|ui| {
+                let Some(available) = renditions.and_then(|map| map.get(&lesson.lesson.id)) else {
+                    return;
+                };
+                let chosen = chosen_quality(preferred, available);
+                ui.horizontal_centered(|ui| {
+                    for quality in available {
+                        let label = RichText::new(quality.label());
+                        if Some(*quality) == chosen {
+                            ui.label(label.strong());
+                        } else {
+                            ui.label(label.weak());
+                        }
+                    }
+                });
+            });
+            row.col(|ui| {
+                if lesson.medias.len() < 2 {
+                    return;
+                }
+                let mut selected: Vec<String> = lesson
+                    .selected_medias(rule)
+                    .into_iter()
+                    .map(|media| media.id.clone())
+                    .collect();
+                ui.horizontal_centered(|ui| {
+                    ui.menu_button(
+                        format!("{}/{}", selected.len(), lesson.medias.len()),
+                        |ui| {
+                            for media in &lesson.medias {
+                                let mut on = selected.contains(&media.id);
+                                // A lesson keeps at least one stream
+                                let last = on && selected.len() == 1;
+                                let checkbox = egui::Checkbox::new(&mut on, media.name());
+                                if ui.add_enabled(!last, checkbox).changed() {
+                                    if on {
+                                        selected.push(media.id.clone());
+                                    } else {
+                                        selected.retain(|id| *id != media.id);
+                                    }
+                                    lesson.streams = Some(selected.clone());
+                                }
+                            }
+                            if lesson.streams.is_some() && ui.button("Use default").clicked() {
+                                lesson.streams = None;
+                            }
+                        },
+                    );
+                });
+            });
             row.col(|ui| {
                 ui.centered_and_justified(|ui| {
-                    ui.add_enabled(show, toggle(&mut lesson.download));
+                    if ui.add_enabled(show, toggle(&mut lesson.download)).changed() {
+                        toggled.set(Some((lesson.index, lesson.download)));
+                    }
                 });
             });
+            row.col(|ui| {
+                let progress =
+                    tracker.and_then(|tracker| Some((tracker, tracker.lesson(&lesson.lesson.id)?)));
+                let Some((tracker, fraction)) = progress else {
+                    if let Some(record) = library.lesson(&lesson.lesson.id) {
+                        ui.horizontal_centered(|ui| {
+                            ui.label(RichText::new("Downloaded").weak())
+                                .on_hover_text(downloaded_at(&record.downloaded));
+                        });
+                    }
+                    return;
+                };
+                let bar = if tracker.lesson_failed(&lesson.lesson.id) {
+                    egui::ProgressBar::new(fraction).text("Failed")
+                } else {
+                    egui::ProgressBar::new(fraction).show_percentage()
+                };
+                ui.add(bar);
+            });
         };
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -255,6 +1198,9 @@ impl App {
                 .column(Column::remainder())
                 .column(Column::auto())
                 .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::exact(120.))
                 .striped(true)
                 .header(20.0, |mut header| {
                     header.col(|ui| {
@@ -263,59 +1209,303 @@ impl App {
                     header.col(|ui| {
                         ui.heading("Time");
                     });
+                    header.col(|ui| {
+                        ui.heading("Quality");
+                    });
+                    header.col(|ui| {
+                        ui.heading("Streams");
+                    });
                     header.col(|ui| {
                         ui.heading("Download");
                     });
+                    header.col(|ui| {
+                        ui.heading("Progress");
+                    });
                 })
                 .body(|mut body| {
-                    for video_data in videos.iter_mut() {
-                        match video_data {
-                            VideoData::SyllabusLessonType { lesson } => {
-                                body.row(30.0, |row| {
-                                    entry(row, lesson);
-                                });
-                            }
-                            VideoData::SyllabusGroupType {
-                                group_info,
-                                lessons,
-                            } => {
-                                body.row(30.0, |mut row| {
-                                    row.col(|ui| {
-                                        ui.horizontal_centered(|ui| {
-                                            ui.heading(&group_info.name);
-                                        });
-                                    });
-                                });
-                                for video_data in lessons {
-                                    match video_data {
-                                        VideoData::SyllabusLessonType { lesson } => {
-                                            body.row(30.0, |row| {
-                                                entry(row, lesson);
-                                            });
-                                        }
-                                        _ => {}
-                                    }
-                                }
-                            }
-                        }
-                    }
+                    syllabus_rows(&mut body, &mut videos, "", 0, &mut self.collapsed, &entry);
                 });
         });
+
+        // Shift-click selects everything from the previous toggle to this one
+        if let Some((index, on)) = toggled.get() {
+            let shift = ctx.input(|input| input.modifiers.shift);
+            if let Some(anchor) = self.selection_anchor.filter(|_| shift) {
+                let range = anchor.min(index)..=anchor.max(index);
+                for lesson in VideoData::flatten_mut(&mut videos) {
+                    if lesson.has_content && range.contains(&lesson.index) {
+                        lesson.download = on;
+                    }
+                }
+            }
+            self.selection_anchor = Some(index);
+        }
     }
 }
 
 impl eframe::App for App {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.cancel_login();
+    }
+
     fn update(&mut self, ctx: &Context, _frame: &mut eframe::Frame) {
+        self.error_panel(ctx);
+
         match self.state {
             AppState::LoggingIn => self.login_screen(ctx),
             AppState::LoadingCourses => self.load_courses(ctx),
             AppState::SelectingCourse => self.course_select_screen(ctx),
             AppState::LoadingVideos => self.load_videos(ctx),
             AppState::SelectingVideos => self.video_select_screen(ctx),
+            AppState::SearchingTranscripts => self.search_screen(ctx),
+            AppState::Reauthenticating => self.reauth_screen(ctx),
         };
     }
 }
 
+const TEMPLATE_HELP: &str = "Placeholders: {course_code} {section_name} {group} \
+{lesson_name} {date} {time} {index} {media_title}\n\
+Dates and times take a format, e.g. {date:%d-%m-%Y}, and {index:02} pads with zeros";
+
+/// Looks up the renditions of every lesson with content, for a background task.
+fn find_renditions(
+    echo360: &Echo360,
+    videos: &[VideoData],
+) -> impl FnOnce() -> HashMap<String, Vec<Quality>> {
+    let client = echo360.client.clone();
+    let domain = echo360.domain.clone();
+    let medias: Vec<(String, Media)> = VideoData::flatten(videos)
+        .into_iter()
+        .filter(|video| video.has_content)
+        .filter_map(|video| Some((video.lesson.id.clone(), video.medias.first()?.clone())))
+        .collect();
+
+    move || {
+        let mut renditions = HashMap::new();
+        for (lesson_id, media) in medias {
+            match media.renditions(&client, &domain) {
+                Ok(available) => {
+                    renditions.insert(lesson_id, available);
+                }
+                Err(err) => log::warn!("could not look up renditions: {err}"),
+            }
+        }
+        renditions
+    }
+}
+
+/// "Downloaded 12/08/2024 14:05" from a library record's RFC 3339 time.
+fn downloaded_at(time: &str) -> String {
+    match DateTime::parse_from_rfc3339(time) {
+        Ok(time) => format!(
+            "Downloaded {}",
+            DateTime::<Local>::from(time).format("%d/%m/%Y %H:%M")
+        ),
+        Err(_) => "Downloaded".to_owned(),
+    }
+}
+
+/// A button showing a section's code, course name, section name and
+/// lesson count.
+fn course_card(ui: &mut Ui, section: &Section) -> egui::Response {
+    let style = ui.style();
+    let mut job = egui::text::LayoutJob::default();
+    let mut line = |text: &str, font: egui::TextStyle, color: egui::Color32| {
+        if !job.text.is_empty() {
+            job.append("\n", 0., egui::TextFormat::default());
+        }
+        job.append(
+            text,
+            0.,
+            egui::TextFormat::simple(font.resolve(style), color),
+        );
+    };
+    let strong = style.visuals.strong_text_color();
+    let weak = style.visuals.weak_text_color();
+    line(&section.course_code, egui::TextStyle::Heading, strong);
+    if !section.course_name.is_empty() {
+        line(&section.course_name, egui::TextStyle::Body, strong);
+    }
+    line(&section.section_name, egui::TextStyle::Body, weak);
+    let lessons = match section.lesson_count {
+        1 => "1 lesson".to_owned(),
+        count => format!("{count} lessons"),
+    };
+    line(&lessons, egui::TextStyle::Small, weak);
+
+    ui.add(egui::Button::new(job).min_size([220., 90.].into()))
+}
+
+/// Shown to accounts without enrollments, such as brand new ones, with the
+/// server's response for diagnosis. Returns whether to reload.
+fn no_courses_screen(ctx: &Context, raw: &str) -> bool {
+    let mut reload = false;
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.vertical_centered(|ui| {
+            ui.add_space(40.);
+            ui.heading("No courses yet");
+            ui.label("This account isn't enrolled in any Echo360 sections.");
+            ui.add_space(10.);
+            reload = ui.button("Reload").clicked();
+        });
+        ui.add_space(20.);
+        egui::CollapsingHeader::new("Server response").show(ui, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.add(egui::Label::new(RichText::new(raw).monospace()).wrap(true));
+            });
+        });
+    });
+    reload
+}
+
+/// The quality a lesson downloads in: the preferred one if it has it, or
+/// else the nearest it does.
+fn chosen_quality(preferred: Quality, available: &[Quality]) -> Option<Quality> {
+    preferred
+        .fallbacks()
+        .into_iter()
+        .find(|quality| available.contains(quality))
+}
+
+/// Roughly how many bytes a lesson's selected streams take, from its length.
+fn estimated_size(
+    lesson: &Video,
+    available: Option<&Vec<Quality>>,
+    preferred: Quality,
+    rule: StreamRule,
+) -> Option<u64> {
+    let seconds = lesson.duration()?.as_secs();
+    let quality = match available {
+        Some(available) => chosen_quality(preferred, available)?,
+        None => preferred,
+    };
+    let size = lesson
+        .selected_medias(rule)
+        .into_iter()
+        .map(|media| match media.kind() {
+            StreamKind::Audio => Quality::Audio.byte_rate(),
+            _ => quality.byte_rate(),
+        })
+        .sum::<u64>()
+        * seconds;
+    Some(size)
+}
+
+/// How far each level of syllabus groups is indented.
+const INDENT: f32 = 20.;
+
+/// Adds a row for every lesson in `data`, under a header row for each group
+/// that folds it away and selects all of it. `parent` is the path of the `depth` enclosing groups.
+fn syllabus_rows(
+    body: &mut TableBody,
+    data: &mut [VideoData],
+    parent: &str,
+    depth: usize,
+    collapsed: &mut HashSet<String>,
+    entry: &impl Fn(TableRow, &mut Video, usize),
+) {
+    for video_data in data {
+        match video_data {
+            VideoData::SyllabusLessonType { lesson } => {
+                body.row(30.0, |row| entry(row, lesson, depth));
+            }
+            VideoData::SyllabusGroupType {
+                group_info,
+                lessons,
+            } => {
+                let path = if parent.is_empty() {
+                    group_info.name.clone()
+                } else {
+                    format!("{parent}/{}", group_info.name)
+                };
+                let open = !collapsed.contains(&path);
+                body.row(30.0, |mut row| {
+                    row.col(|ui| {
+                        ui.horizontal_centered(|ui| {
+                            ui.add_space(depth as f32 * INDENT);
+                            let arrow = if open { "▼" } else { "▶" };
+                            if ui.small_button(arrow).clicked() && !collapsed.remove(&path) {
+                                collapsed.insert(path.clone());
+                            }
+                            ui.heading(&group_info.name);
+                        });
+                    });
+                    // Time, quality and streams
+                    for _ in 0..3 {
+                        row.col(|_| {});
+                    }
+                    row.col(|ui| {
+                        let mut members: Vec<&mut Video> = VideoData::flatten_mut(lessons)
+                            .into_iter()
+                            .filter(|lesson| lesson.has_content)
+                            .collect();
+                        let mut all =
+                            !members.is_empty() && members.iter().all(|lesson| lesson.download);
+                        ui.centered_and_justified(|ui| {
+                            let response = ui.add_enabled(!members.is_empty(), toggle(&mut all));
+                            if response.on_hover_text("Select the whole group").changed() {
+                                members.iter_mut().for_each(|lesson| lesson.download = all);
+                            }
+                        });
+                    });
+                });
+                if open {
+                    syllabus_rows(body, lessons, &path, depth + 1, collapsed, entry);
+                }
+            }
+        }
+    }
+}
+
+/// Logs an error and hands it back for the error panel.
+fn failed(err: impl Into<Error>) -> Option<Error> {
+    let err = err.into();
+    log::error!("{err}");
+    Some(err)
+}
+
+/// Heading with a spinner and a Cancel button. Returns whether Cancel was clicked.
+fn loading_screen(ctx: &Context, heading: &str) -> bool {
+    let mut cancel = false;
+
+    egui::TopBottomPanel::top("Top Panel").show(ctx, |ui| {
+        ui.vertical_centered(|ui| {
+            ui.add_space(10.0);
+            ui.label(RichText::new(heading).size(32.0).heading().strong());
+            ui.add_space(10.0);
+        });
+    });
+
+    egui::CentralPanel::default().show(ctx, |ui| {
+        ui.vertical_centered(|ui| {
+            ui.add_space(20.0);
+            ui.add(egui::Spinner::new().size(39.0));
+            ui.add_space(20.0);
+            cancel = ui.button("Cancel").clicked();
+        });
+    });
+
+    // Keep polling the background task
+    ctx.request_repaint_after(Duration::from_millis(100));
+    cancel
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
 fn toggle_ui(ui: &mut egui::Ui, on: &mut bool) -> egui::Response {
     let desired_size = ui.spacing().interact_size.y * egui::vec2(2.0, 1.0);
     let (rect, mut response) = ui.allocate_exact_size(desired_size, egui::Sense::click());
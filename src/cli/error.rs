@@ -0,0 +1,33 @@
+use derive_more::From;
+
+use crate::echo360;
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+#[derive(Debug, From)]
+pub enum Error {
+    UnknownSection(String),
+    NothingSelected,
+    DownloadsFailed(usize),
+    BrokenFiles(usize),
+
+    // -- Modules
+    #[from]
+    Echo360(echo360::Error),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownSection(section) => write!(f, "no enrolled section matches '{section}'"),
+            Self::NothingSelected => write!(f, "pass --all, --since or --ids to choose lessons"),
+            Self::DownloadsFailed(count) => write!(f, "{count} downloads failed"),
+            Self::BrokenFiles(count) => {
+                write!(f, "{count} files are broken, pass --fix to discard them")
+            }
+            Self::Echo360(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
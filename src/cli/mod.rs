@@ -0,0 +1,387 @@
+mod error;
+pub use error::{Error, Result};
+
+mod watch;
+
+use std::{path::PathBuf, sync::atomic::AtomicBool, time::Duration};
+
+use chrono::NaiveDate;
+use clap::{Args, Parser, Subcommand};
+
+use crate::echo360::{
+    self,
+    captions::{self, CaptionFormat},
+    courses::{Enrollments, Section},
+    download::{self, DownloadOptions, ExistingFiles, Layout, Report},
+    library::Library,
+    sync,
+    template::Template,
+    transcripts::Index,
+    videos::{Quality, StreamRule, Video, VideoData},
+    Echo360,
+};
+
+/// Download Echo360 lecture recordings. Runs the GUI when no subcommand is given.
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// List enrolled course sections
+    Courses {
+        /// Print the enrollments response as received instead
+        #[arg(long)]
+        raw: bool,
+    },
+    /// List the lessons of a section
+    Lessons {
+        /// Section id, course code or section name
+        section: String,
+        /// Also list the qualities available for each lesson
+        #[arg(long)]
+        renditions: bool,
+    },
+    /// Download lessons of a section
+    Download {
+        /// Section id, course code or section name
+        section: String,
+        /// Download every lesson with content
+        #[arg(long, conflicts_with_all = ["since", "ids"])]
+        all: bool,
+        /// Download lessons recorded on or after this date (YYYY-MM-DD)
+        #[arg(long, conflicts_with = "ids")]
+        since: Option<NaiveDate>,
+        /// Download the lessons with these ids
+        #[arg(long, num_args = 1..)]
+        ids: Vec<String>,
+        #[command(flatten)]
+        options: DownloadArgs,
+    },
+    /// Download the lessons of a section that earlier syncs to the same
+    /// directory haven't fetched
+    Sync {
+        /// Section id, course code or section name
+        section: String,
+        #[command(flatten)]
+        options: DownloadArgs,
+    },
+    /// Keep syncing sections, downloading new lessons as they're published
+    Watch {
+        /// Section ids, course codes or section names; every enrolled
+        /// section when none are given
+        sections: Vec<String>,
+        /// Minutes between checks
+        #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u64).range(1..))]
+        interval: u64,
+        #[command(flatten)]
+        rules: watch::Rules,
+        #[command(flatten)]
+        options: DownloadArgs,
+    },
+    /// Re-check downloaded files against the library, no login needed
+    Verify {
+        /// Directory the videos were downloaded to
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
+        /// Delete broken files so the next sync downloads them again
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Search downloaded captions, no login needed
+    Search {
+        /// Words that must all appear in a caption
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Directory the captions were downloaded to
+        #[arg(short, long, default_value = ".")]
+        dir: PathBuf,
+    },
+}
+
+/// Where and how to save downloads.
+#[derive(Args)]
+pub struct DownloadArgs {
+    /// Directory to save the videos to
+    #[arg(short, long, default_value = ".")]
+    out: PathBuf,
+    /// Also download captions
+    #[arg(long)]
+    captions: bool,
+    /// Formats to save captions in
+    #[arg(long, value_enum, value_delimiter = ',', default_value = "vtt")]
+    caption_formats: Vec<CaptionFormat>,
+    /// Number of parallel downloads
+    #[arg(short, long, default_value_t = download::DEFAULT_WORKERS)]
+    jobs: usize,
+    /// File name pattern, see the README for placeholders
+    #[arg(short, long, default_value = Template::DEFAULT)]
+    template: Template,
+    /// What to do with files that are already downloaded
+    #[arg(long, value_enum, default_value_t)]
+    existing: ExistingFiles,
+    /// Preferred quality, falling back to the nearest available one
+    #[arg(short, long, value_enum, default_value_t)]
+    quality: Quality,
+    /// Which streams of each lesson to download
+    #[arg(long, value_enum, default_value_t)]
+    streams: StreamRule,
+    /// Whether to put files in folders by course, section and syllabus group
+    #[arg(long, value_enum, default_value_t)]
+    layout: Layout,
+}
+
+impl From<DownloadArgs> for DownloadOptions {
+    fn from(args: DownloadArgs) -> Self {
+        Self {
+            path: args.out,
+            template: args.template,
+            captions: args.captions,
+            caption_formats: args.caption_formats,
+            workers: args.jobs,
+            existing: args.existing,
+            quality: args.quality,
+            streams: args.streams,
+            layout: args.layout,
+        }
+    }
+}
+
+pub fn run(command: Command) -> Result<()> {
+    if let Command::Search { query, dir } = &command {
+        let index = Index::build(dir)?;
+        for hit in index.search(&query.join(" ")) {
+            println!(
+                "{}\t{}\t{}",
+                hit.transcript.lesson,
+                captions::clock(hit.cue.start, '.'),
+                hit.cue.text.replace('\n', " ")
+            );
+        }
+        return Ok(());
+    }
+    if let Command::Verify { dir, fix } = &command {
+        let problems = Library::load(dir)?.verify(dir);
+        for problem in &problems {
+            println!("{}: {}", problem.path.display(), problem.reason);
+        }
+        if *fix && !problems.is_empty() {
+            Library::repair(dir, &problems)?;
+        }
+        if problems.is_empty() {
+            println!("all files are intact");
+        } else if *fix {
+            println!(
+                "deleted {} broken files, sync to download them again",
+                problems.len()
+            );
+        } else {
+            return Err(Error::BrokenFiles(problems.len()));
+        }
+        return Ok(());
+    }
+
+    let mut echo360 = Echo360::login(&AtomicBool::new(false))?;
+    let enrollments =
+        echo360.retry_expired(|echo360| Enrollments::get(&echo360.client, &echo360.domain))?;
+
+    match command {
+        Command::Courses { raw: true } => println!("{}", enrollments.raw),
+        Command::Courses { raw: false } => {
+            if enrollments.user_sections.is_empty() {
+                println!("No enrollments, pass --raw to see what the server sent");
+            }
+            for (term, sections) in enrollments.by_term() {
+                let term = term.map_or("", |term| term.name.as_str());
+                for section in sections {
+                    println!(
+                        "{}\t{}\t{}\t{}\t{} lessons\t{}",
+                        section.section_id,
+                        section.course_code,
+                        section.course_name,
+                        section.section_name,
+                        section.lesson_count,
+                        term
+                    );
+                }
+            }
+        }
+        Command::Lessons {
+            section,
+            renditions,
+        } => {
+            let section = find_section(&enrollments, &section)?;
+            let videos = echo360.retry_expired(|echo360| {
+                Video::get_videos(&echo360.client, &echo360.domain, &section.section_id)
+            })?;
+            for video in VideoData::flatten(&videos) {
+                let date = video
+                    .start_time()
+                    .ok()
+                    .flatten()
+                    .map(|start| start.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "---".to_owned());
+                let mut note = if video.has_content {
+                    String::new()
+                } else {
+                    " (no content)".to_owned()
+                };
+                if video.medias.len() > 1 {
+                    let streams: Vec<&str> =
+                        video.medias.iter().map(|media| media.name()).collect();
+                    note += &format!(" ({})", streams.join(", "));
+                }
+                if renditions {
+                    if let Some(media) = video.medias.first() {
+                        let qualities: Vec<&str> = media
+                            .renditions(&echo360.client, &echo360.domain)?
+                            .into_iter()
+                            .map(Quality::label)
+                            .collect();
+                        note += &format!(" [{}]", qualities.join(", "));
+                    }
+                }
+                println!(
+                    "{}\t{}\t{}{}",
+                    video.lesson.id, date, video.lesson.display_name, note
+                );
+            }
+        }
+        Command::Download {
+            section,
+            all,
+            since,
+            ids,
+            options,
+        } => {
+            if !all && since.is_none() && ids.is_empty() {
+                return Err(Error::NothingSelected);
+            }
+
+            let section = find_section(&enrollments, &section)?;
+            let videos = echo360.retry_expired(|echo360| {
+                Video::get_videos(&echo360.client, &echo360.domain, &section.section_id)
+            })?;
+            let syllabus = VideoData::flatten(&videos);
+            let downloads: Vec<&Video> = syllabus
+                .iter()
+                .copied()
+                .filter(|video| video.has_content)
+                .filter(|video| {
+                    all || ids.contains(&video.lesson.id)
+                        || since.is_some_and(|since| {
+                            video
+                                .start_time()
+                                .ok()
+                                .flatten()
+                                .is_some_and(|start| start.date_naive() >= since)
+                        })
+                })
+                .collect();
+
+            let options = DownloadOptions::from(options);
+            println!(
+                "Downloading {} lessons to {}",
+                downloads.len(),
+                options.path.display()
+            );
+            let report = echo360.download_videos(section, &syllabus, &downloads, &options)?;
+            summarize(&report)?;
+        }
+        Command::Sync { section, options } => {
+            let section = find_section(&enrollments, &section)?;
+            let options = DownloadOptions::from(options);
+            let report = sync_section(&mut echo360, section, &options, |_| true)?;
+            summarize(&report)?;
+        }
+        Command::Watch {
+            sections,
+            interval,
+            rules,
+            options,
+        } => watch::run(
+            &mut echo360,
+            &sections,
+            Duration::from_secs(interval.saturating_mul(60)),
+            &rules,
+            &DownloadOptions::from(options),
+        ),
+        Command::Search { .. } | Command::Verify { .. } => {
+            unreachable!("handled before logging in")
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads the lessons of a section that earlier syncs to the same folder
+/// haven't fetched and `keep` accepts, and updates the folder's manifest.
+fn sync_section(
+    echo360: &mut Echo360,
+    section: &Section,
+    options: &DownloadOptions,
+    keep: impl Fn(&Video) -> bool,
+) -> Result<Report> {
+    let videos = echo360.retry_expired(|echo360| {
+        Video::get_videos(&echo360.client, &echo360.domain, &section.section_id)
+    })?;
+    let videos = VideoData::flatten(&videos);
+
+    let report = sync::sync(
+        &options.path,
+        &section.section_id,
+        &videos,
+        keep,
+        |pending| {
+            println!(
+                "{}: {} new lessons to download to {}",
+                section.course_code,
+                pending.len(),
+                options.path.display()
+            );
+            echo360.download_videos(section, &videos, pending, options)
+        },
+    )?;
+    Ok(report)
+}
+
+/// Prints failed files and a count, failing if any file failed.
+fn summarize(report: &Report) -> Result<()> {
+    for outcome in report.failed() {
+        if let Err(err) = &outcome.result {
+            eprintln!("failed: {}: {err}", outcome.job.path.display());
+        }
+    }
+    println!(
+        "{} of {} files downloaded",
+        report.succeeded(),
+        report.outcomes.len()
+    );
+    if !report.is_ok() {
+        return Err(Error::DownloadsFailed(
+            report.outcomes.len() - report.succeeded(),
+        ));
+    }
+    Ok(())
+}
+
+/// Finds an enrolled section by id, course code or name.
+fn find_section<'a>(enrollments: &'a Enrollments, query: &str) -> Result<&'a Section> {
+    if enrollments.user_sections.is_empty() {
+        return Err(echo360::Error::NoEnrollments.into());
+    }
+    enrollments
+        .user_sections
+        .iter()
+        .find(|section| section.section_id == query)
+        .or_else(|| {
+            enrollments.user_sections.iter().find(|section| {
+                section.course_code.eq_ignore_ascii_case(query)
+                    || section.section_name.eq_ignore_ascii_case(query)
+            })
+        })
+        .ok_or_else(|| Error::UnknownSection(query.to_owned()))
+}
@@ -0,0 +1,122 @@
+use std::{thread, time::Duration};
+
+use chrono::{Local, NaiveDate};
+use clap::Args;
+
+use super::{find_section, sync_section, Result};
+use crate::echo360::{
+    courses::{Enrollments, Section},
+    download::DownloadOptions,
+    videos::Video,
+    Echo360,
+};
+
+/// The shortest pause after a failure, which later failures double.
+const MIN_BACKOFF: Duration = Duration::from_secs(60);
+/// The longest pause after repeated failures.
+const MAX_BACKOFF: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Which new lessons `watch` downloads.
+#[derive(Args)]
+pub struct Rules {
+    /// Only lessons recorded on or after this date (YYYY-MM-DD)
+    #[arg(long)]
+    since: Option<NaiveDate>,
+    /// Only lessons whose name contains this, ignoring case; repeat to allow
+    /// several
+    #[arg(long = "name")]
+    names: Vec<String>,
+}
+
+impl Rules {
+    fn keep(&self, video: &Video) -> bool {
+        let recent = self.since.is_none_or(|since| {
+            video
+                .start_time()
+                .ok()
+                .flatten()
+                .is_some_and(|start| start.date_naive() >= since)
+        });
+        let name = video.lesson.display_name.to_lowercase();
+        let named = self.names.is_empty()
+            || self
+                .names
+                .iter()
+                .any(|wanted| name.contains(&wanted.to_lowercase()));
+        recent && named
+    }
+}
+
+/// Syncs the sections every `interval`, forever. Failed rounds are retried
+/// after twice the previous pause, from at least [`MIN_BACKOFF`] up to
+/// [`MAX_BACKOFF`].
+pub fn run(
+    echo360: &mut Echo360,
+    sections: &[String],
+    interval: Duration,
+    rules: &Rules,
+    options: &DownloadOptions,
+) -> ! {
+    let mut pause = interval;
+    loop {
+        match check(echo360, sections, rules, options) {
+            Ok(()) => pause = interval,
+            Err(err) => {
+                pause = (pause.max(MIN_BACKOFF) * 2).min(MAX_BACKOFF.max(interval));
+                note(&format!(
+                    "check failed: {err}, retrying in {} minutes",
+                    pause.as_secs() / 60
+                ));
+            }
+        }
+        thread::sleep(pause);
+    }
+}
+
+/// One round: syncs every watched section, carrying on past failures.
+fn check(
+    echo360: &mut Echo360,
+    sections: &[String],
+    rules: &Rules,
+    options: &DownloadOptions,
+) -> Result<()> {
+    let enrollments =
+        echo360.retry_expired(|echo360| Enrollments::get(&echo360.client, &echo360.domain))?;
+    let watched: Vec<Section> = if sections.is_empty() {
+        enrollments.user_sections.clone()
+    } else {
+        sections
+            .iter()
+            .map(|query| find_section(&enrollments, query).cloned())
+            .collect::<Result<_>>()?
+    };
+
+    let mut first_error = None;
+    for section in &watched {
+        match sync_section(echo360, section, options, |video| rules.keep(video)) {
+            Ok(report) => {
+                let failed = report.outcomes.len() - report.succeeded();
+                note(&format!(
+                    "{}: {} files downloaded, {failed} failed",
+                    section.course_code,
+                    report.succeeded()
+                ));
+                for outcome in report.failed() {
+                    if let Err(err) = &outcome.result {
+                        note(&format!("failed: {}: {err}", outcome.job.path.display()));
+                    }
+                }
+            }
+            Err(err) => {
+                note(&format!("{}: {err}", section.course_code));
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Prints a line with the local time, as watch output ends up in logs.
+fn note(message: &str) {
+    println!("[{}] {message}", Local::now().format("%Y-%m-%d %H:%M:%S"));
+}
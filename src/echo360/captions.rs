@@ -0,0 +1,285 @@
+use std::time::Duration;
+
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+
+use super::{Error, Result};
+
+/// A pause this long between cues starts a new paragraph in plain text.
+const PARAGRAPH_GAP: Duration = Duration::from_secs(2);
+
+/// A file format captions can be saved in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum CaptionFormat {
+    /// WebVTT, as served
+    Vtt,
+    /// SubRip
+    Srt,
+    /// Paragraphs without timestamps
+    Txt,
+    /// A list of cues with start and end in seconds
+    Json,
+}
+
+impl CaptionFormat {
+    pub const ALL: [Self; 4] = [Self::Vtt, Self::Srt, Self::Txt, Self::Json];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Vtt => "WebVTT",
+            Self::Srt => "SRT",
+            Self::Txt => "Text",
+            Self::Json => "JSON",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Vtt => "vtt",
+            Self::Srt => "srt",
+            Self::Txt => "txt",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Cue {
+    #[serde(serialize_with = "seconds")]
+    pub start: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub end: Duration,
+    /// Text with markup removed, lines joined by `\n`.
+    pub text: String,
+}
+
+/// Reads the cues of a WebVTT file, skipping notes, styles and regions.
+pub fn parse_vtt(vtt: &str) -> Result<Vec<Cue>> {
+    let vtt = vtt.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut blocks = vtt
+        .split("\n\n")
+        .map(str::trim)
+        .filter(|block| !block.is_empty());
+    if !blocks
+        .next()
+        .is_some_and(|header| header.starts_with("WEBVTT"))
+    {
+        return Err(Error::Captions("missing WEBVTT header".to_owned()));
+    }
+
+    let mut cues = Vec::new();
+    for block in blocks {
+        let mut lines = block.lines();
+        // An optional identifier comes before the timings
+        let Some(timings) = lines.by_ref().find(|line| line.contains("-->")) else {
+            continue;
+        };
+        let (start, rest) = timings.split_once("-->").unwrap();
+        // Cue settings may follow the end time
+        let end = rest.split_whitespace().next().unwrap_or_default();
+        let text: Vec<String> = lines.map(strip_markup).collect();
+        cues.push(Cue {
+            start: timestamp(start.trim())?,
+            end: timestamp(end)?,
+            text: text.join("\n"),
+        });
+    }
+    Ok(cues)
+}
+
+/// Writes cues out in `format`.
+pub fn render(cues: &[Cue], format: CaptionFormat) -> String {
+    match format {
+        CaptionFormat::Vtt => {
+            let mut out = "WEBVTT\n".to_owned();
+            for cue in cues {
+                out += &format!(
+                    "\n{} --> {}\n{}\n",
+                    clock(cue.start, '.'),
+                    clock(cue.end, '.'),
+                    cue.text
+                );
+            }
+            out
+        }
+        CaptionFormat::Srt => {
+            let mut out = String::new();
+            for (number, cue) in cues.iter().enumerate() {
+                out += &format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    number + 1,
+                    clock(cue.start, ','),
+                    clock(cue.end, ','),
+                    cue.text
+                );
+            }
+            out
+        }
+        CaptionFormat::Txt => paragraphs(cues),
+        CaptionFormat::Json => serde_json::to_string_pretty(cues).unwrap(),
+    }
+}
+
+/// Joins cue text into paragraphs, dropping lines repeated by rolling
+/// captions and breaking wherever the speaker pauses.
+fn paragraphs(cues: &[Cue]) -> String {
+    let mut paragraphs: Vec<Vec<&str>> = Vec::new();
+    let mut previous_end = None;
+    let mut previous_line = "";
+    for cue in cues {
+        let pause = previous_end.is_some_and(|end| cue.start.saturating_sub(end) >= PARAGRAPH_GAP);
+        if pause || paragraphs.is_empty() {
+            paragraphs.push(Vec::new());
+        }
+        previous_end = Some(cue.end);
+        for line in cue.text.lines().map(str::trim) {
+            if line.is_empty() || line == previous_line {
+                continue;
+            }
+            previous_line = line;
+            paragraphs.last_mut().unwrap().push(line);
+        }
+    }
+    let text: Vec<String> = paragraphs
+        .into_iter()
+        .filter(|lines| !lines.is_empty())
+        .map(|lines| lines.join(" "))
+        .collect();
+    text.join("\n\n") + "\n"
+}
+
+/// `01:02:03.456` or `02:03.456`
+fn timestamp(value: &str) -> Result<Duration> {
+    let bad = || Error::Captions(format!("bad timestamp '{value}'"));
+    let (clock, millis) = value.split_once('.').ok_or_else(bad)?;
+    let mut seconds = 0;
+    for part in clock.split(':') {
+        seconds = seconds * 60 + part.parse::<u64>().map_err(|_| bad())?;
+    }
+    let millis: u64 = millis.parse().map_err(|_| bad())?;
+    Ok(Duration::from_millis(seconds * 1000 + millis))
+}
+
+/// `HH:MM:SS.mmm`, with `separator` before the milliseconds.
+pub fn clock(time: Duration, separator: char) -> String {
+    let millis = time.as_millis();
+    format!(
+        "{:02}:{:02}:{:02}{separator}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Drops tags such as `<v Speaker>` and `<c.yellow>`, and decodes entities.
+fn strip_markup(line: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn seconds<S: Serializer>(time: &Duration, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VTT: &str = "\u{feff}WEBVTT - lecture\r
+\r
+NOTE recorded in room 101\r
+\r
+STYLE\r
+::cue { color: yellow }\r
+\r
+1\r
+00:00:01.000 --> 00:00:03.500 align:start\r
+<v Lecturer>Good morning &amp; welcome.</v>\r
+\r
+00:03.500 --> 00:00:05.000\r
+Good morning &amp; welcome.\r
+Today: <c.yellow>limits</c>\r
+\r
+01:00:07.250 --> 01:00:09.000\r
+After the break\r
+";
+
+    fn cue(start: u64, end: u64, text: &str) -> Cue {
+        Cue {
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+            text: text.to_owned(),
+        }
+    }
+
+    fn cues() -> Vec<Cue> {
+        vec![
+            cue(1000, 3500, "Good morning & welcome."),
+            cue(3500, 5000, "Good morning & welcome.\nToday: limits"),
+            cue(3_607_250, 3_609_000, "After the break"),
+        ]
+    }
+
+    #[test]
+    fn parses_webvtt() {
+        assert_eq!(parse_vtt(VTT).unwrap(), cues());
+        assert_eq!(parse_vtt("WEBVTT\n").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_what_isnt_webvtt() {
+        assert!(matches!(
+            parse_vtt("<html>Not found</html>"),
+            Err(Error::Captions(_))
+        ));
+        assert!(matches!(parse_vtt(""), Err(Error::Captions(_))));
+        assert!(matches!(
+            parse_vtt("WEBVTT\n\n00:00:aa.000 --> 00:00:02.000\nHi\n"),
+            Err(Error::Captions(_))
+        ));
+    }
+
+    #[test]
+    fn renders_each_format() {
+        let cues = cues();
+        assert_eq!(
+            render(&cues[..1], CaptionFormat::Vtt),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:03.500\nGood morning & welcome.\n"
+        );
+        assert_eq!(
+            render(&cues[..2], CaptionFormat::Srt),
+            "1\n00:00:01,000 --> 00:00:03,500\nGood morning & welcome.\n\n\
+             2\n00:00:03,500 --> 00:00:05,000\nGood morning & welcome.\nToday: limits\n\n"
+        );
+        // Repeated lines dropped, a new paragraph after the pause
+        assert_eq!(
+            render(&cues, CaptionFormat::Txt),
+            "Good morning & welcome. Today: limits\n\nAfter the break\n"
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&cues[..1], CaptionFormat::Json)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([{"start": 1.0, "end": 3.5, "text": "Good morning & welcome."}])
+        );
+    }
+
+    #[test]
+    fn round_trips_through_webvtt() {
+        let cues = cues();
+        assert_eq!(parse_vtt(&render(&cues, CaptionFormat::Vtt)).unwrap(), cues);
+    }
+}
@@ -1,34 +1,52 @@
 use reqwest::blocking::Client;
 use serde::Deserialize;
 
-use super::Result;
+use super::{decode_raw, Result};
 
 #[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Section {
     // course_id: String,
     pub course_code: String,
-    // course_name: String,
-    // lesson_count: usize,
+    #[serde(default)]
+    pub course_name: String,
+    #[serde(default)]
+    pub lesson_count: usize,
     pub section_id: String,
     pub section_name: String,
-    // term_id: String,
+    #[serde(default)]
+    pub term_id: String,
 }
 
-// #[derive(Debug, Default, Deserialize)]
-// #[serde(rename_all = "camelCase")]
-// struct Term {
-//     id: String,
-//     is_active: bool,
-//     is_active_or_future: bool,
-//     name: String,
-//     start_date: String,
-// }
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Term {
+    pub id: String,
+    #[serde(default)]
+    pub is_active: bool,
+    #[serde(default)]
+    pub is_active_or_future: bool,
+    pub name: String,
+    /// e.g. `2024-07-22`
+    #[serde(default)]
+    pub start_date: String,
+}
+
+impl Term {
+    pub fn is_past(&self) -> bool {
+        !self.is_active && !self.is_active_or_future
+    }
+}
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Enrollments {
     pub user_sections: Vec<Section>,
+    #[serde(default)]
+    pub user_terms: Vec<Term>,
+    /// The response these came from, for diagnosing odd accounts.
+    #[serde(skip)]
+    pub raw: String,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -39,17 +57,91 @@ struct EnrollmentsResponse {
     data: Vec<Enrollments>,
 }
 
+impl Section {
+    /// Whether the code, course name or section name contains `query`,
+    /// ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim().to_lowercase();
+        [&self.course_code, &self.course_name, &self.section_name]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&query))
+    }
+}
+
 impl Enrollments {
-    const REQUEST_PATH: &'static str = "/user/enrollments";
+    pub const REQUEST_PATH: &'static str = "/user/enrollments";
+
+    pub fn term(&self, section: &Section) -> Option<&Term> {
+        self.user_terms
+            .iter()
+            .find(|term| term.id == section.term_id)
+    }
+
+    /// Sections grouped by term: active terms, then future ones, then past
+    /// ones, each newest first. Sections of unknown terms come last.
+    pub fn by_term(&self) -> Vec<(Option<&Term>, Vec<&Section>)> {
+        let mut terms: Vec<&Term> = self.user_terms.iter().collect();
+        terms.sort_by(|a, b| {
+            let rank = |term: &Term| (!term.is_active, term.is_past());
+            rank(a)
+                .cmp(&rank(b))
+                .then_with(|| b.start_date.cmp(&a.start_date))
+        });
 
+        let mut groups: Vec<(Option<&Term>, Vec<&Section>)> = terms
+            .into_iter()
+            .map(|term| {
+                let sections: Vec<&Section> = self
+                    .user_sections
+                    .iter()
+                    .filter(|section| section.term_id == term.id)
+                    .collect();
+                (Some(term), sections)
+            })
+            .filter(|(_, sections)| !sections.is_empty())
+            .collect();
+        let other: Vec<&Section> = self
+            .user_sections
+            .iter()
+            .filter(|section| self.term(section).is_none())
+            .collect();
+        if !other.is_empty() {
+            groups.push((None, other));
+        }
+        groups
+    }
+
+    /// Merges every enrollment block in the response, dropping sections and
+    /// terms listed more than once. New accounts may have none at all.
     pub fn get(client: &Client, domain: impl Into<String>) -> Result<Self> {
-        let EnrollmentsResponse { mut data, .. } = dbg!(client
-            .get(&(domain.into() + Self::REQUEST_PATH))
-            .send()
-            .unwrap())
-        .json::<EnrollmentsResponse>()
-        .unwrap();
-
-        Ok(dbg!(data.remove(0)))
+        let response = client.get(&(domain.into() + Self::REQUEST_PATH)).send()?;
+        let (EnrollmentsResponse { data, .. }, raw) = decode_raw(response)?;
+
+        let mut enrollments = Self {
+            raw,
+            ..Default::default()
+        };
+        for block in data {
+            for section in block.user_sections {
+                let known = enrollments
+                    .user_sections
+                    .iter()
+                    .any(|known| known.section_id == section.section_id);
+                if !known {
+                    enrollments.user_sections.push(section);
+                }
+            }
+            for term in block.user_terms {
+                if !enrollments
+                    .user_terms
+                    .iter()
+                    .any(|known| known.id == term.id)
+                {
+                    enrollments.user_terms.push(term);
+                }
+            }
+        }
+        log::debug!("{enrollments:?}");
+        Ok(enrollments)
     }
 }
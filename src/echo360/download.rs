@@ -0,0 +1,641 @@
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use reqwest::{blocking::Client, header, StatusCode, Url};
+use sha2::{Digest, Sha256};
+
+use super::{
+    authenticated,
+    captions::{self, CaptionFormat},
+    check_status,
+    courses::Section,
+    hls::{HlsClient, MediaPlaylist},
+    integrity,
+    template::{sanitize, Template},
+    videos::{Quality, StreamRule, Video},
+    Error, Result,
+};
+
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// What to do when a file being downloaded is already on disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ExistingFiles {
+    /// Keep the file on disk
+    #[default]
+    Skip,
+    /// Download it again
+    Overwrite,
+    /// Save under a new name next to it
+    Rename,
+    /// Download it again if the size differs from the server's
+    CompareSize,
+}
+
+impl ExistingFiles {
+    pub const ALL: [Self; 4] = [Self::Skip, Self::Overwrite, Self::Rename, Self::CompareSize];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Skip => "Skip",
+            Self::Overwrite => "Overwrite",
+            Self::Rename => "Rename",
+            Self::CompareSize => "Compare size",
+        }
+    }
+}
+
+/// How downloaded files are arranged in the download folder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Layout {
+    /// Everything directly in the folder
+    #[default]
+    Flat,
+    /// A folder per course, section and syllabus group
+    Folders,
+}
+
+impl Layout {
+    pub const ALL: [Self; 2] = [Self::Flat, Self::Folders];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Flat => "Flat",
+            Self::Folders => "Folders",
+        }
+    }
+
+    /// The folder, relative to the download folder, that a lesson's files go
+    /// in, e.g. `COMP1234/Lecture A/Week 3`.
+    pub fn folder(self, section: &Section, video: &Video) -> PathBuf {
+        match self {
+            Self::Flat => PathBuf::new(),
+            Self::Folders => [&section.course_code, &section.section_name]
+                .into_iter()
+                .chain(&video.groups)
+                .map(|name| sanitize(name))
+                .collect(),
+        }
+    }
+}
+
+/// How and where to save the lessons chosen for download.
+#[derive(Clone, Debug)]
+pub struct DownloadOptions {
+    pub path: PathBuf,
+    pub template: Template,
+    pub captions: bool,
+    /// Formats to save captions in, when `captions` is set.
+    pub caption_formats: Vec<CaptionFormat>,
+    pub workers: usize,
+    pub existing: ExistingFiles,
+    pub quality: Quality,
+    pub streams: StreamRule,
+    pub layout: Layout,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::new(),
+            template: Template::default(),
+            captions: false,
+            caption_formats: vec![CaptionFormat::Vtt],
+            workers: DEFAULT_WORKERS,
+            existing: ExistingFiles::default(),
+            quality: Quality::default(),
+            streams: StreamRule::default(),
+            layout: Layout::default(),
+        }
+    }
+}
+
+/// How often a running job reports its progress.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where a job's bytes come from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    /// `url` is the file itself.
+    File,
+    /// `url` is an HLS playlist, whose variant closest to the quality was
+    /// resolved to these segments while planning. They are joined into one
+    /// file.
+    Hls(MediaPlaylist),
+    /// `url` is a WebVTT file, saved in each format next to `path` with the
+    /// format's extension.
+    Captions(Vec<CaptionFormat>),
+}
+
+/// A single file to fetch.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub lesson_id: String,
+    pub media_id: String,
+    pub url: String,
+    pub source: Source,
+    pub path: PathBuf,
+    /// Only replace an existing file whose size differs from the server's.
+    pub compare_size: bool,
+}
+
+#[derive(Debug)]
+pub struct Outcome {
+    pub job: Job,
+    /// Bytes written on success.
+    pub result: Result<u64>,
+}
+
+/// The jobs to run for a download, and the outputs that couldn't be planned.
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub jobs: Vec<Job>,
+    /// Failed outcomes of outputs whose name or source couldn't be worked out.
+    pub failed: Vec<Outcome>,
+}
+
+/// Results of a [`Downloader::run`], in the same order as the jobs, followed
+/// by the outputs that couldn't be planned.
+#[derive(Debug, Default)]
+pub struct Report {
+    pub outcomes: Vec<Outcome>,
+}
+
+impl Report {
+    pub fn succeeded(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &Outcome> {
+        self.outcomes.iter().filter(|o| o.result.is_err())
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.failed().next().is_none()
+    }
+
+    /// Lessons with a file that failed because the session expired, to try
+    /// again after logging back in.
+    pub fn expired_lessons(&self) -> HashSet<String> {
+        self.failed()
+            .filter(|outcome| matches!(outcome.result, Err(Error::SessionExpired)))
+            .map(|outcome| outcome.job.lesson_id.clone())
+            .collect()
+    }
+
+    /// Swaps the outcomes of `lessons` for those of a retry of them.
+    pub fn replace(&mut self, lessons: &HashSet<String>, retry: Report) {
+        self.outcomes
+            .retain(|outcome| !lessons.contains(&outcome.job.lesson_id));
+        self.outcomes.extend(retry.outcomes);
+    }
+}
+
+/// A snapshot of one job's transfer.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// Index of the job in the list passed to [`Downloader::run`].
+    pub job: usize,
+    pub received: u64,
+    /// From Content-Length, including any resumed bytes.
+    pub total: Option<u64>,
+    /// Bytes per second over the current attempt.
+    pub speed: f64,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> Option<f32> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| self.received as f32 / total as f32)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The jobs about to run, in order.
+    Started(Vec<Job>),
+    Progress(Progress),
+    Finished {
+        job: usize,
+        ok: bool,
+    },
+}
+
+/// Runs jobs on a pool of worker threads sharing one authenticated client.
+pub struct Downloader<'a> {
+    client: &'a Client,
+    hls: HlsClient<'a>,
+    workers: usize,
+    events: Option<Sender<Event>>,
+}
+
+impl<'a> Downloader<'a> {
+    pub fn new(client: &'a Client, domain: &str, workers: usize) -> Self {
+        Self {
+            client,
+            hls: HlsClient::new(client, domain),
+            workers: workers.max(1),
+            events: None,
+        }
+    }
+
+    /// Sends [`Event`]s for every job to `events` while running.
+    pub fn with_progress(mut self, events: Sender<Event>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Runs every job, continuing past failures. Outputs that couldn't be
+    /// planned are reported as failed straight away.
+    pub fn run(&self, plan: Plan) -> Report {
+        let Plan { jobs, failed } = plan;
+        let count = jobs.len();
+        let all = jobs.iter().chain(failed.iter().map(|outcome| &outcome.job));
+        self.send(Event::Started(all.cloned().collect()));
+        for job in count..count + failed.len() {
+            self.send(Event::Finished { job, ok: false });
+        }
+
+        let queue = Mutex::new(jobs.into_iter().enumerate());
+        let results = Mutex::new(Vec::with_capacity(count));
+
+        thread::scope(|scope| {
+            for _ in 0..self.workers.min(count) {
+                scope.spawn(|| loop {
+                    let Some((index, job)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let result = self.fetch(index, &job);
+                    if let Err(err) = &result {
+                        log::warn!("{} failed: {err}", job.path.display());
+                    }
+                    self.send(Event::Finished {
+                        job: index,
+                        ok: result.is_ok(),
+                    });
+                    results
+                        .lock()
+                        .unwrap()
+                        .push((index, Outcome { job, result }));
+                });
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(index, _)| *index);
+        Report {
+            outcomes: results
+                .into_iter()
+                .map(|(_, outcome)| outcome)
+                .chain(failed)
+                .collect(),
+        }
+    }
+
+    fn send(&self, event: Event) {
+        if let Some(events) = &self.events {
+            // The receiver going away just means nobody is watching
+            let _ = events.send(event);
+        }
+    }
+
+    /// Downloads into a `.part` file next to the destination, resuming from
+    /// whatever an earlier attempt left behind, and renames it into place once
+    /// the transfer completes.
+    fn fetch(&self, index: usize, job: &Job) -> Result<u64> {
+        if let Some(dir) = job.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match &job.source {
+            Source::File => {}
+            Source::Hls(playlist) => return self.fetch_hls(index, job, playlist),
+            Source::Captions(formats) => return self.fetch_captions(job, formats),
+        }
+        if job.compare_size && self.same_size(job)? {
+            log::info!("{} is up to date", job.path.display());
+            return Ok(0);
+        }
+
+        let part = part_path(&job.path);
+        let offset = fs::metadata(&part).map(|meta| meta.len()).unwrap_or(0);
+
+        let mut request = self.client.get(&job.url);
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={offset}-"));
+        }
+        let mut response = authenticated(request.send()?)?;
+
+        let (file, offset) = match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                log::debug!("resuming {} at {offset} bytes", job.path.display());
+                (OpenOptions::new().append(true).open(&part)?, offset)
+            }
+            // The partial file already holds everything the server has
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                finish(&part, &job.path)?;
+                return Ok(0);
+            }
+            // Range unsupported, or nothing to resume: start over
+            _ => {
+                response = check_status(response)?;
+                (File::create(&part)?, 0)
+            }
+        };
+
+        let mut writer = ProgressWriter {
+            file,
+            downloader: self,
+            progress: Progress {
+                job: index,
+                received: offset,
+                total: response.content_length().map(|len| len + offset),
+                speed: 0.,
+            },
+            offset,
+            started: Instant::now(),
+            last_sent: Instant::now(),
+        };
+        let written = io::copy(&mut response, &mut writer)?;
+        writer.report();
+        let Progress {
+            received, total, ..
+        } = writer.progress.clone();
+        drop(writer);
+
+        // The connection can close early without an error. The partial file
+        // stays, so the next attempt resumes it.
+        if let Some(total) = total.filter(|total| *total != received) {
+            return Err(Error::Corrupt {
+                path: job.path.clone(),
+                reason: format!("got {received} of {total} bytes"),
+            });
+        }
+        finish(&part, &job.path)?;
+        Ok(written)
+    }
+
+    /// Fetches the segments of an HLS stream next to the destination, keeping
+    /// any finished by an earlier attempt, and joins them into place.
+    fn fetch_hls(&self, index: usize, job: &Job, playlist: &MediaPlaylist) -> Result<u64> {
+        // There's no size to compare against, so an existing file stays
+        if job.compare_size && job.path.exists() {
+            log::info!("{} is already downloaded", job.path.display());
+            return Ok(0);
+        }
+        let segments = playlist.segments.len() + usize::from(playlist.init.is_some());
+
+        // Segments of another variant can't be joined with these
+        let segments_dir = segments_path(&job.path, &playlist.url);
+        for stale in stale_segments(&job.path, &segments_dir) {
+            log::debug!("removing stale segments {}", stale.display());
+            fs::remove_dir_all(stale)?;
+        }
+
+        let part = part_path(&job.path);
+        let started = Instant::now();
+        let written = playlist.download(
+            &self.hls,
+            &segments_dir,
+            &mut File::create(&part)?,
+            |received, done| {
+                self.send(Event::Progress(Progress {
+                    job: index,
+                    received,
+                    // Segments are about the same size, so the ones done so
+                    // far give an estimate
+                    total: Some(received * segments as u64 / done as u64),
+                    speed: received as f64 / started.elapsed().as_secs_f64().max(f64::EPSILON),
+                }));
+            },
+        )?;
+
+        finish(&part, &job.path)?;
+        Ok(written)
+    }
+
+    /// Downloads WebVTT captions and writes each requested format. They are
+    /// small enough to fetch again rather than compare sizes. A lesson without
+    /// a usable transcript just has no captions, rather than failing.
+    fn fetch_captions(&self, job: &Job, formats: &[CaptionFormat]) -> Result<u64> {
+        let response = authenticated(self.client.get(&job.url).send()?)?;
+        if response.status() == StatusCode::NOT_FOUND {
+            log::warn!("no captions for {}", job.path.display());
+            return Ok(0);
+        }
+        let vtt = check_status(response)?.text()?;
+        let cues = match captions::parse_vtt(&vtt) {
+            Ok(cues) => cues,
+            Err(err) => {
+                log::warn!("no usable captions for {}: {err}", job.path.display());
+                return Ok(0);
+            }
+        };
+
+        let mut written = 0;
+        for format in formats {
+            let contents = match format {
+                // Keep styling and cue settings the conversion would drop
+                CaptionFormat::Vtt => vtt.clone(),
+                _ => captions::render(&cues, *format),
+            };
+            fs::write(job.path.with_extension(format.extension()), &contents)?;
+            written += contents.len() as u64;
+        }
+        Ok(written)
+    }
+
+    /// Whether the file on disk is as big as the server says it should be.
+    fn same_size(&self, job: &Job) -> Result<bool> {
+        let Ok(meta) = fs::metadata(&job.path) else {
+            return Ok(false);
+        };
+        let response = check_status(authenticated(self.client.head(&job.url).send()?)?)?;
+        Ok(response.content_length() == Some(meta.len()))
+    }
+}
+
+/// Counts bytes on their way to disk and periodically reports them.
+struct ProgressWriter<'a, 'b> {
+    file: File,
+    downloader: &'b Downloader<'a>,
+    progress: Progress,
+    offset: u64,
+    started: Instant,
+    last_sent: Instant,
+}
+
+impl ProgressWriter<'_, '_> {
+    fn report(&mut self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        if elapsed > 0. {
+            self.progress.speed = (self.progress.received - self.offset) as f64 / elapsed;
+        }
+        self.last_sent = Instant::now();
+        self.downloader.send(Event::Progress(self.progress.clone()));
+    }
+}
+
+impl Write for ProgressWriter<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.file.write(buf)?;
+        self.progress.received += written as u64;
+        if self.last_sent.elapsed() >= PROGRESS_INTERVAL {
+            self.report();
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Folds [`Event`]s into per-job state for display.
+#[derive(Default)]
+pub struct Tracker {
+    pub jobs: Vec<Job>,
+    progress: Vec<Option<Progress>>,
+    finished: Vec<Option<bool>>,
+}
+
+impl Tracker {
+    pub fn update(&mut self, event: Event) {
+        match event {
+            Event::Started(jobs) => {
+                self.progress = vec![None; jobs.len()];
+                self.finished = vec![None; jobs.len()];
+                self.jobs = jobs;
+            }
+            Event::Progress(progress) => {
+                let job = progress.job;
+                self.progress[job] = Some(progress);
+            }
+            Event::Finished { job, ok } => self.finished[job] = Some(ok),
+        }
+    }
+
+    fn fraction(&self, job: usize) -> f32 {
+        match self.finished[job] {
+            Some(_) => 1.,
+            None => self.progress[job]
+                .as_ref()
+                .and_then(Progress::fraction)
+                .unwrap_or(0.),
+        }
+    }
+
+    fn lesson_jobs<'a>(&'a self, lesson_id: &'a str) -> impl Iterator<Item = usize> + 'a {
+        (0..self.jobs.len()).filter(move |&job| self.jobs[job].lesson_id == lesson_id)
+    }
+
+    /// Completion of every job belonging to a lesson, if it has any.
+    pub fn lesson(&self, lesson_id: &str) -> Option<f32> {
+        let fractions: Vec<f32> = self
+            .lesson_jobs(lesson_id)
+            .map(|job| self.fraction(job))
+            .collect();
+        (!fractions.is_empty()).then(|| fractions.iter().sum::<f32>() / fractions.len() as f32)
+    }
+
+    /// Whether any job of a lesson failed.
+    pub fn lesson_failed(&self, lesson_id: &str) -> bool {
+        self.lesson_jobs(lesson_id)
+            .any(|job| self.finished[job] == Some(false))
+    }
+
+    /// Completion of all jobs, and the combined speed (bytes per second) and
+    /// ETA of the running ones.
+    pub fn overall(&self) -> (f32, f64, Option<Duration>) {
+        if self.jobs.is_empty() {
+            return (1., 0., None);
+        }
+        let fraction = (0..self.jobs.len())
+            .map(|job| self.fraction(job))
+            .sum::<f32>()
+            / self.jobs.len() as f32;
+
+        let (speed, remaining) = (0..self.jobs.len())
+            .filter(|&job| self.finished[job].is_none())
+            .filter_map(|job| self.progress[job].as_ref())
+            .fold((0., 0), |(speed, remaining), progress| {
+                let left = progress
+                    .total
+                    .map_or(0, |total| total.saturating_sub(progress.received));
+                (speed + progress.speed, remaining + left)
+            });
+        let eta = (speed > 0.).then(|| Duration::from_secs_f64(remaining as f64 / speed));
+
+        (fraction, speed, eta)
+    }
+}
+
+/// Checks the structure of a finished MP4 and moves it into place. A
+/// malformed file is thrown away, as resuming it won't help.
+fn finish(part: &Path, path: &Path) -> Result<()> {
+    if integrity::is_mp4(path) {
+        if let Err(err) = integrity::check_mp4(part) {
+            fs::remove_file(part)?;
+            return Err(match err {
+                Error::Corrupt { reason, .. } => Error::Corrupt {
+                    path: path.to_owned(),
+                    reason,
+                },
+                err => err,
+            });
+        }
+    }
+    fs::rename(part, path)?;
+    Ok(())
+}
+
+/// `lecture.ts` -> `lecture.ts.1a2b3c4d.segments`, where HLS segments wait to
+/// be joined, keyed by the playlist they come from
+fn segments_path(path: &Path, playlist: &Url) -> PathBuf {
+    let digest = Sha256::digest(playlist.as_str().as_bytes());
+    let key: String = digest[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(".{key}.segments"));
+    path.with_file_name(name)
+}
+
+/// Segment folders left next to `path` by other playlists than `current`'s.
+fn stale_segments(path: &Path, current: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (path.parent(), path.file_name()) else {
+        return Vec::new();
+    };
+    let prefix = format!("{}.", name.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|other| other != current && other.is_dir())
+        .filter(|other| {
+            other.file_name().is_some_and(|other| {
+                let other = other.to_string_lossy();
+                other.starts_with(&prefix) && other.ends_with(".segments")
+            })
+        })
+        .collect()
+}
+
+/// `lecture.mp4` -> `lecture.mp4.part`
+fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(".part");
+    path.with_file_name(name)
+}
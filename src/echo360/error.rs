@@ -1,13 +1,54 @@
 use derive_more::From;
+use reqwest::StatusCode;
 use thirtyfour::error::WebDriverError;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(Debug, From)]
 pub enum Error {
+    /// The session cookie is no longer accepted.
+    SessionExpired,
+    /// The browser login was cancelled before it finished.
+    LoginCancelled,
+    /// The server answered with an error status.
+    Status { url: String, status: StatusCode },
+    /// The response wasn't the JSON we expected.
+    Decode {
+        url: String,
+        source: serde_json::Error,
+        /// Start of the response body.
+        snippet: String,
+    },
+    /// A lesson with content but no media to download.
+    NoMedia { lesson: String },
+    /// The account isn't enrolled in anything.
+    NoEnrollments,
+    BadTimestamp {
+        value: String,
+        source: chrono::ParseError,
+    },
+    /// A cookie that can't be sent as a header.
+    BadCookie,
+    /// An HLS playlist we can't download from.
+    Playlist { url: String, reason: String },
+    /// Captions that aren't valid WebVTT.
+    Captions(String),
+    /// A downloaded file that is incomplete or malformed.
+    Corrupt {
+        path: std::path::PathBuf,
+        reason: String,
+    },
+    /// A sync manifest or download library that isn't valid JSON.
+    BadManifest {
+        path: std::path::PathBuf,
+        source: serde_json::Error,
+    },
+    /// A file name template that doesn't parse.
+    Template(String),
+
     // -- Externals
     #[from]
-    WebDriver(WebDriverError),
+    WebDriver(Box<WebDriverError>),
 
     #[from]
     Reqwest(reqwest::Error),
@@ -15,3 +56,52 @@ pub enum Error {
     #[from]
     Io(std::io::Error),
 }
+
+impl From<WebDriverError> for Error {
+    fn from(err: WebDriverError) -> Self {
+        Self::WebDriver(Box::new(err))
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SessionExpired => write!(f, "session expired, log in again"),
+            Self::LoginCancelled => write!(f, "login cancelled"),
+            Self::Status { url, status } => write!(f, "{url} returned {status}"),
+            Self::Decode {
+                url,
+                source,
+                snippet,
+            } => write!(f, "unexpected response from {url}: {source}\n{snippet}"),
+            Self::NoMedia { lesson } => write!(f, "'{lesson}' has no media to download"),
+            Self::NoEnrollments => write!(f, "this account has no enrollments"),
+            Self::BadTimestamp { value, source } => write!(f, "bad timestamp '{value}': {source}"),
+            Self::BadCookie => write!(f, "session cookie is not a valid header"),
+            Self::Playlist { url, reason } => write!(f, "playlist {url}: {reason}"),
+            Self::Captions(reason) => write!(f, "captions: {reason}"),
+            Self::Corrupt { path, reason } => write!(f, "{} is broken: {reason}", path.display()),
+            Self::BadManifest { path, source } => {
+                write!(f, "unreadable {}: {source}", path.display())
+            }
+            Self::Template(reason) => write!(f, "file name template: {reason}"),
+            Self::WebDriver(err) => write!(f, "webdriver: {err}"),
+            Self::Reqwest(err) => write!(f, "http: {err}"),
+            Self::Io(err) => write!(f, "io: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Decode { source, .. } => Some(source),
+            Self::BadTimestamp { source, .. } => Some(source),
+            Self::BadManifest { source, .. } => Some(source),
+            Self::WebDriver(err) => Some(err),
+            Self::Reqwest(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,660 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use reqwest::{
+    blocking::{Client, Response},
+    Url,
+};
+
+use super::{authenticated, check_status, videos::Quality, Error, Result};
+
+/// Segments fetched at once for a single playlist.
+const SEGMENT_WORKERS: usize = 4;
+/// Attempts per segment before the whole download fails.
+const SEGMENT_ATTEMPTS: u32 = 3;
+
+/// One stream listed in a master playlist.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub url: Url,
+    /// Bits per second, from `BANDWIDTH`.
+    pub bandwidth: u64,
+    /// Picture height, from `RESOLUTION`.
+    pub height: Option<u32>,
+    /// Whether `CODECS` lists only audio.
+    pub audio_only: bool,
+    /// Whether its sound is a separate rendition (`EXT-X-MEDIA` with a URI),
+    /// which isn't downloaded.
+    pub separate_audio: bool,
+}
+
+/// The segments of a single stream, in playback order.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MediaPlaylist {
+    /// Where the playlist was fetched from, after redirects.
+    pub url: Url,
+    /// Initialisation section (`EXT-X-MAP`) of fragmented MP4 streams.
+    pub init: Option<Url>,
+    pub segments: Vec<Url>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Playlist {
+    Master(Vec<Variant>),
+    Media(MediaPlaylist),
+}
+
+/// Sends the session cookie only to the Echo360 domain, as playlists and
+/// segments may come from a CDN that has no business seeing it.
+pub struct HlsClient<'a> {
+    session: &'a Client,
+    host: Option<String>,
+    public: Client,
+}
+
+impl<'a> HlsClient<'a> {
+    pub fn new(session: &'a Client, domain: &str) -> Self {
+        Self {
+            session,
+            host: Url::parse(domain)
+                .ok()
+                .and_then(|domain| domain.host_str().map(str::to_owned)),
+            public: Client::new(),
+        }
+    }
+
+    fn get(&self, url: &Url) -> Result<Response> {
+        let client = match url.host_str() == self.host.as_deref() {
+            true => self.session,
+            false => &self.public,
+        };
+        Ok(client.get(url.clone()).send()?)
+    }
+}
+
+impl Playlist {
+    /// Parses an `.m3u8`, resolving relative URIs against `base`.
+    pub fn parse(base: &Url, text: &str) -> Result<Self> {
+        let bad = |reason: &str| Error::Playlist {
+            url: base.to_string(),
+            reason: reason.to_owned(),
+        };
+        let join = |uri: &str| base.join(uri).map_err(|_| bad(&format!("bad URI '{uri}'")));
+
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some("#EXTM3U") {
+            return Err(bad("missing #EXTM3U header"));
+        }
+
+        let mut variants = Vec::new();
+        let mut media = MediaPlaylist {
+            url: base.clone(),
+            init: None,
+            segments: Vec::new(),
+        };
+        // Audio groups of each variant, and the groups with their own files
+        let mut audio_groups = Vec::new();
+        let mut separate_audio = HashSet::new();
+        // Attributes of the `EXT-X-STREAM-INF` waiting for its URI
+        let mut stream_inf = None;
+        for line in lines {
+            if let Some(attrs) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+                stream_inf = Some(attributes(attrs));
+            } else if let Some(attrs) = line.strip_prefix("#EXT-X-MEDIA:") {
+                let attrs = attributes(attrs);
+                if lookup(&attrs, "TYPE").as_deref() == Some("AUDIO")
+                    && lookup(&attrs, "URI").is_some()
+                {
+                    separate_audio.extend(lookup(&attrs, "GROUP-ID"));
+                }
+            } else if let Some(attrs) = line.strip_prefix("#EXT-X-MAP:") {
+                let uri = lookup(&attributes(attrs), "URI")
+                    .ok_or_else(|| bad("EXT-X-MAP without URI"))?;
+                media.init = Some(join(&uri)?);
+            } else if let Some(attrs) = line.strip_prefix("#EXT-X-KEY:") {
+                if lookup(&attributes(attrs), "METHOD").as_deref() != Some("NONE") {
+                    return Err(bad("encrypted streams are not supported"));
+                }
+            } else if line.starts_with("#EXT-X-BYTERANGE") {
+                return Err(bad("byte range segments are not supported"));
+            } else if line.starts_with('#') {
+                // Other tags and comments don't change what gets fetched
+            } else if let Some(attrs) = stream_inf.take() {
+                let height = lookup(&attrs, "RESOLUTION")
+                    .and_then(|resolution| resolution.split_once('x')?.1.parse().ok());
+                let audio_only = lookup(&attrs, "CODECS").is_some_and(|codecs| {
+                    codecs
+                        .split(',')
+                        .all(|codec| codec.trim().starts_with("mp4a"))
+                });
+                audio_groups.push(lookup(&attrs, "AUDIO"));
+                variants.push(Variant {
+                    url: join(line)?,
+                    bandwidth: lookup(&attrs, "BANDWIDTH")
+                        .and_then(|bandwidth| bandwidth.parse().ok())
+                        .unwrap_or(0),
+                    height,
+                    audio_only,
+                    // Known once every `EXT-X-MEDIA` is read
+                    separate_audio: false,
+                });
+            } else {
+                media.segments.push(join(line)?);
+            }
+        }
+
+        for (variant, group) in variants.iter_mut().zip(audio_groups) {
+            variant.separate_audio = group.is_some_and(|group| separate_audio.contains(&group));
+        }
+
+        match (variants.is_empty(), media.segments.is_empty()) {
+            (false, _) => Ok(Self::Master(variants)),
+            (true, false) => Ok(Self::Media(media)),
+            (true, true) => Err(bad("no streams or segments")),
+        }
+    }
+
+    pub fn fetch(client: &HlsClient, url: &Url) -> Result<Self> {
+        let response = check_status(authenticated(client.get(url)?)?)?;
+        // Redirects move the base that relative URIs resolve against
+        let base = response.url().clone();
+        Self::parse(&base, &response.text()?)
+    }
+}
+
+impl MediaPlaylist {
+    /// Follows a master playlist to the variant closest to `quality`, out of
+    /// those with their sound in the same stream.
+    pub fn resolve(client: &HlsClient, url: &Url, quality: Quality) -> Result<Self> {
+        match Playlist::fetch(client, url)? {
+            Playlist::Media(media) => Ok(media),
+            Playlist::Master(mut variants) => {
+                variants.retain(|variant| !variant.separate_audio);
+                let variant = pick_variant(&variants, quality).ok_or_else(|| Error::Playlist {
+                    url: url.to_string(),
+                    reason: "audio in separate renditions is not supported".to_owned(),
+                })?;
+                log::debug!("using HLS variant {}", variant.url);
+                match Playlist::fetch(client, &variant.url)? {
+                    Playlist::Media(media) => Ok(media),
+                    Playlist::Master(_) => Err(Error::Playlist {
+                        url: variant.url.to_string(),
+                        reason: "nested master playlist".to_owned(),
+                    }),
+                }
+            }
+        }
+    }
+
+    /// Fragmented MP4 streams concatenate into an MP4, the rest into MPEG-TS.
+    pub fn extension(&self) -> &'static str {
+        if self.init.is_some() {
+            "mp4"
+        } else {
+            "ts"
+        }
+    }
+
+    /// Fetches every segment into `dir`, keeping ones an earlier attempt
+    /// finished, then joins them in order into `out`. `progress` is called
+    /// with the bytes fetched so far and the number of segments done.
+    pub fn download(
+        &self,
+        client: &HlsClient,
+        dir: &Path,
+        out: &mut impl Write,
+        progress: impl Fn(u64, usize) + Sync,
+    ) -> Result<u64> {
+        fs::create_dir_all(dir)?;
+        let urls: Vec<&Url> = self.init.iter().chain(&self.segments).collect();
+        let queue = Mutex::new(urls.iter().enumerate());
+        let done = Mutex::new((0, 0));
+        let failure = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for _ in 0..SEGMENT_WORKERS.min(urls.len()) {
+                scope.spawn(|| loop {
+                    if failure.lock().unwrap().is_some() {
+                        break;
+                    }
+                    let Some((index, url)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    match fetch_segment(client, url, &segment_path(dir, index)) {
+                        Ok(bytes) => {
+                            let mut done = done.lock().unwrap();
+                            *done = (done.0 + bytes, done.1 + 1);
+                            progress(done.0, done.1);
+                        }
+                        Err(err) => {
+                            failure.lock().unwrap().get_or_insert(err);
+                        }
+                    }
+                });
+            }
+        });
+        if let Some(err) = failure.into_inner().unwrap() {
+            return Err(err);
+        }
+
+        let mut written = 0;
+        for index in 0..urls.len() {
+            written += io::copy(&mut File::open(segment_path(dir, index))?, out)?;
+        }
+        out.flush()?;
+        fs::remove_dir_all(dir)?;
+        Ok(written)
+    }
+}
+
+/// The tallest video at or below `quality`'s height, else the shortest above
+/// it. Audio prefers an audio-only stream, then the lowest bandwidth.
+pub fn pick_variant(variants: &[Variant], quality: Quality) -> Option<&Variant> {
+    let Some(target) = quality.height() else {
+        return variants
+            .iter()
+            .min_by_key(|variant| (!variant.audio_only, variant.bandwidth));
+    };
+    let video = || variants.iter().filter(|variant| !variant.audio_only);
+    let key = |variant: &&Variant| (variant.height.unwrap_or(0), variant.bandwidth);
+    video()
+        .filter(|variant| variant.height.unwrap_or(0) <= target)
+        .max_by_key(key)
+        .or_else(|| video().min_by_key(key))
+        .or_else(|| variants.first())
+}
+
+/// Fetches one segment to `path`, retrying with a growing pause. Segments
+/// are written under a temporary name, so anything at `path` is complete.
+fn fetch_segment(client: &HlsClient, url: &Url, path: &Path) -> Result<u64> {
+    if let Ok(meta) = fs::metadata(path) {
+        return Ok(meta.len());
+    }
+    let partial = path.with_extension("tmp");
+    let mut attempt = 1;
+    loop {
+        let result = (|| {
+            let mut response = check_status(authenticated(client.get(url)?)?)?;
+            let bytes = io::copy(&mut response, &mut File::create(&partial)?)?;
+            fs::rename(&partial, path)?;
+            Ok(bytes)
+        })();
+        match result {
+            // Retrying won't bring a session back
+            Err(Error::SessionExpired) => return result,
+            Err(err) if attempt < SEGMENT_ATTEMPTS => {
+                log::debug!("segment {url} failed, retrying: {err}");
+                thread::sleep(Duration::from_secs(attempt.into()));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn segment_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{index:06}.seg"))
+}
+
+/// Splits an attribute list such as `BANDWIDTH=800000,CODECS="avc1,mp4a"`.
+fn attributes(list: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    let mut rest = list;
+    while let Some((name, after)) = rest.split_once('=') {
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, after)) => (value, after),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        attrs.push((name.trim().to_owned(), value.to_owned()));
+        rest = after.trim_start_matches(',');
+    }
+    attrs
+}
+
+fn lookup(attrs: &[(String, String)], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        sync::Arc,
+    };
+
+    use reqwest::header;
+
+    use super::*;
+
+    const MASTER: &str = "#EXTM3U
+#EXT-X-VERSION:3
+#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"
+audio/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=854x480,CODECS=\"avc1.4d401e,mp4a.40.2\"
+480p/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720,CODECS=\"avc1.4d401f,mp4a.40.2\"
+720p/index.m3u8
+";
+
+    const MEDIA: &str = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXT-X-MAP:URI=\"init.mp4\"
+#EXTINF:10.0,
+seg0.m4s
+
+#EXTINF:10.0,
+seg1.m4s
+#EXTINF:4.2,
+/other/seg2.m4s
+#EXT-X-ENDLIST
+";
+
+    fn url(url: &str) -> Url {
+        Url::parse(url).unwrap()
+    }
+
+    fn variant(height: Option<u32>, bandwidth: u64, audio_only: bool) -> Variant {
+        Variant {
+            url: url(&format!("https://example.com/{bandwidth}.m3u8")),
+            bandwidth,
+            height,
+            audio_only,
+            separate_audio: false,
+        }
+    }
+
+    #[test]
+    fn parses_master_playlists() {
+        let base = url("https://example.com/lesson/master.m3u8");
+        let Playlist::Master(variants) = Playlist::parse(&base, MASTER).unwrap() else {
+            panic!("expected a master playlist");
+        };
+        assert_eq!(
+            variants,
+            vec![
+                Variant {
+                    url: url("https://example.com/lesson/audio/index.m3u8"),
+                    bandwidth: 64000,
+                    height: None,
+                    audio_only: true,
+                    separate_audio: false,
+                },
+                Variant {
+                    url: url("https://example.com/lesson/480p/index.m3u8"),
+                    bandwidth: 800000,
+                    height: Some(480),
+                    audio_only: false,
+                    separate_audio: false,
+                },
+                Variant {
+                    url: url("https://example.com/lesson/720p/index.m3u8"),
+                    bandwidth: 2500000,
+                    height: Some(720),
+                    audio_only: false,
+                    separate_audio: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_media_playlists() {
+        let base = url("https://example.com/lesson/720p/index.m3u8");
+        let playlist = Playlist::parse(&base, MEDIA).unwrap();
+        assert_eq!(
+            playlist,
+            Playlist::Media(MediaPlaylist {
+                url: base.clone(),
+                init: Some(url("https://example.com/lesson/720p/init.mp4")),
+                segments: vec![
+                    url("https://example.com/lesson/720p/seg0.m4s"),
+                    url("https://example.com/lesson/720p/seg1.m4s"),
+                    url("https://example.com/other/seg2.m4s"),
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_playlists() {
+        let base = url("https://example.com/index.m3u8");
+        let rejects = |text: &str| Playlist::parse(&base, text).is_err();
+        assert!(rejects("seg0.ts\n"));
+        assert!(rejects("#EXTM3U\n#EXT-X-ENDLIST\n"));
+        assert!(rejects(
+            "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\nseg0.ts\n"
+        ));
+        assert!(rejects("#EXTM3U\n#EXT-X-BYTERANGE:1000@0\nseg0.ts\n"));
+        assert!(!rejects("#EXTM3U\n#EXT-X-KEY:METHOD=NONE\nseg0.ts\n"));
+    }
+
+    #[test]
+    fn notices_separate_audio() {
+        let base = url("https://example.com/master.m3u8");
+        let text = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"audio.m3u8\"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"muxed\",NAME=\"English\"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=854x480,AUDIO=\"aac\"
+480p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=400000,RESOLUTION=640x360,AUDIO=\"muxed\"
+360p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=300000,RESOLUTION=640x360
+plain.m3u8
+";
+        let Playlist::Master(variants) = Playlist::parse(&base, text).unwrap() else {
+            panic!("expected a master playlist");
+        };
+        let separate: Vec<bool> = variants
+            .iter()
+            .map(|variant| variant.separate_audio)
+            .collect();
+        assert_eq!(separate, vec![true, false, false]);
+    }
+
+    #[test]
+    fn skips_variants_without_their_sound() {
+        let master = "#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"audio.m3u8\"
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720,AUDIO=\"aac\"
+720p.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=854x480
+480p.m3u8
+";
+        let (base, _) = serve(HashMap::from([
+            ("/master.m3u8", master.as_bytes().to_vec()),
+            ("/480p.m3u8", b"#EXTM3U\n#EXTINF:10.0,\nseg0.ts\n".to_vec()),
+        ]));
+        let session = Client::new();
+        let client = HlsClient::new(&session, base.as_str());
+        let playlist =
+            MediaPlaylist::resolve(&client, &base.join("master.m3u8").unwrap(), Quality::Hd1)
+                .unwrap();
+        assert_eq!(playlist.url, base.join("480p.m3u8").unwrap());
+
+        // Nothing else to fall back on
+        let (base, _) = serve(HashMap::from([(
+            "/master.m3u8",
+            master
+                .lines()
+                .take(4)
+                .collect::<Vec<_>>()
+                .join("\n")
+                .into_bytes(),
+        )]));
+        let client = HlsClient::new(&session, base.as_str());
+        let result =
+            MediaPlaylist::resolve(&client, &base.join("master.m3u8").unwrap(), Quality::Hd1);
+        assert!(matches!(result, Err(Error::Playlist { .. })));
+    }
+
+    #[test]
+    fn splits_attribute_lists() {
+        let attrs =
+            attributes("BANDWIDTH=800000,CODECS=\"avc1.4d401e,mp4a.40.2\",RESOLUTION=854x480");
+        assert_eq!(
+            attrs,
+            vec![
+                ("BANDWIDTH".to_owned(), "800000".to_owned()),
+                ("CODECS".to_owned(), "avc1.4d401e,mp4a.40.2".to_owned()),
+                ("RESOLUTION".to_owned(), "854x480".to_owned()),
+            ]
+        );
+        assert_eq!(lookup(&attrs, "RESOLUTION").as_deref(), Some("854x480"));
+        assert_eq!(lookup(&attrs, "URI"), None);
+        assert_eq!(
+            attributes("URI=\"unterminated"),
+            vec![("URI".to_owned(), "unterminated".to_owned())]
+        );
+    }
+
+    #[test]
+    fn picks_the_closest_variant() {
+        let variants = [
+            variant(None, 64_000, true),
+            variant(Some(480), 800_000, false),
+            variant(Some(720), 2_500_000, false),
+            variant(Some(720), 3_000_000, false),
+        ];
+        let pick = |quality| pick_variant(&variants, quality).unwrap().bandwidth;
+        assert_eq!(pick(Quality::Sd), 800_000);
+        assert_eq!(pick(Quality::Hd1), 3_000_000);
+        assert_eq!(pick(Quality::Hd2), 3_000_000);
+        assert_eq!(pick(Quality::Audio), 64_000);
+
+        // Nothing low enough: the smallest above
+        let tall = [
+            variant(Some(1080), 5_000_000, false),
+            variant(Some(720), 2_500_000, false),
+        ];
+        assert_eq!(pick_variant(&tall, Quality::Sd).unwrap().height, Some(720));
+        // No audio-only stream: the cheapest
+        assert_eq!(
+            pick_variant(&tall, Quality::Audio).unwrap().bandwidth,
+            2_500_000
+        );
+        assert_eq!(pick_variant(&[], Quality::Sd), None);
+    }
+
+    /// Serves `files` by path over HTTP on a local port, answering 404 to
+    /// anything else. Returns the server's URL and the requests it receives.
+    fn serve(files: HashMap<&'static str, Vec<u8>>) -> (Url, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = url(&format!("http://{}/", listener.local_addr().unwrap()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                let mut head = request.clone();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    head.push_str(&line);
+                    line.clear();
+                }
+                received.lock().unwrap().push(head);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match files.get(path) {
+                    Some(body) => ("200 OK", body.clone()),
+                    None => ("404 Not Found", Vec::new()),
+                };
+                let head = format!(
+                    "HTTP/1.1 {status}\r\nContent-Type: application/octet-stream\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+        (base, requests)
+    }
+
+    #[test]
+    fn downloads_from_a_server() {
+        let (base, _) = serve(HashMap::from([
+            ("/master.m3u8", MASTER.as_bytes().to_vec()),
+            ("/720p/index.m3u8", MEDIA.as_bytes().to_vec()),
+            ("/720p/init.mp4", b"init-".to_vec()),
+            ("/720p/seg0.m4s", b"zero-".to_vec()),
+            ("/720p/seg1.m4s", b"one-".to_vec()),
+            ("/other/seg2.m4s", b"two".to_vec()),
+        ]));
+        let session = Client::new();
+        let client = HlsClient::new(&session, base.as_str());
+        let playlist =
+            MediaPlaylist::resolve(&client, &base.join("master.m3u8").unwrap(), Quality::Hd1)
+                .unwrap();
+        assert_eq!(playlist.extension(), "mp4");
+        assert_eq!(playlist.segments.len(), 3);
+
+        let dir = std::env::temp_dir().join(format!("echo360-hls-test-{}", std::process::id()));
+        let mut out = Vec::new();
+        let calls = Mutex::new(0);
+        let written = playlist
+            .download(&client, &dir, &mut out, |_, _| *calls.lock().unwrap() += 1)
+            .unwrap();
+        assert_eq!(out, b"init-zero-one-two");
+        assert_eq!(written, out.len() as u64);
+        assert_eq!(*calls.lock().unwrap(), 4);
+        assert!(!dir.exists(), "segments are cleaned up");
+    }
+
+    #[test]
+    fn sends_the_cookie_only_to_the_domain() {
+        let (base, requests) = serve(HashMap::from([(
+            "/index.m3u8",
+            b"#EXTM3U\n#EXTINF:10.0,\nseg0.ts\n".to_vec(),
+        )]));
+        let mut headers = header::HeaderMap::new();
+        headers.insert("Cookie", "PLAY_SESSION=secret".parse().unwrap());
+        let session = Client::builder().default_headers(headers).build().unwrap();
+        let playlist = base.join("index.m3u8").unwrap();
+        let sent_cookie = || {
+            let head = requests.lock().unwrap().pop().unwrap().to_lowercase();
+            head.contains("play_session=secret")
+        };
+
+        Playlist::fetch(&HlsClient::new(&session, base.as_str()), &playlist).unwrap();
+        assert!(sent_cookie());
+        // Served from elsewhere, like a CDN
+        Playlist::fetch(&HlsClient::new(&session, "https://echo360.org"), &playlist).unwrap();
+        assert!(!sent_cookie());
+    }
+
+    #[test]
+    fn fails_on_missing_segments() {
+        let (base, _) = serve(HashMap::from([(
+            "/index.m3u8",
+            b"#EXTM3U\n#EXTINF:10.0,\nmissing.ts\n".to_vec(),
+        )]));
+        let session = Client::new();
+        let client = HlsClient::new(&session, base.as_str());
+        let playlist =
+            MediaPlaylist::resolve(&client, &base.join("index.m3u8").unwrap(), Quality::Sd)
+                .unwrap();
+        assert_eq!(playlist.extension(), "ts");
+
+        let dir = std::env::temp_dir().join(format!("echo360-hls-fail-{}", std::process::id()));
+        let result = playlist.download(&client, &dir, &mut Vec::new(), |_, _| {});
+        assert!(matches!(result, Err(Error::Status { .. })));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
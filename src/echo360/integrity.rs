@@ -0,0 +1,163 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use super::{Error, Result};
+
+/// Whether `path` names an MP4 file, whose structure can be checked.
+pub fn is_mp4(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("mp4"))
+}
+
+/// Walks the top-level boxes of an MP4, failing unless it starts with `ftyp`,
+/// has a `moov` and every box ends within the file.
+pub fn check_mp4(path: &Path) -> Result<()> {
+    let corrupt = |reason: String| Error::Corrupt {
+        path: path.to_owned(),
+        reason,
+    };
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut offset = 0;
+    let mut first = None;
+    let mut has_moov = false;
+    while offset < len {
+        if len - offset < 8 {
+            return Err(corrupt(format!("truncated box header at byte {offset}")));
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let kind = String::from_utf8_lossy(&header[4..]).into_owned();
+        let size = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            // The box runs to the end of the file
+            0 => len - offset,
+            // A 64-bit size follows the type
+            1 => {
+                let mut large = [0; 8];
+                file.read_exact(&mut large)
+                    .map_err(|_| corrupt(format!("truncated '{kind}' box header")))?;
+                u64::from_be_bytes(large)
+            }
+            size => size.into(),
+        };
+        if size < 8 || offset.checked_add(size).is_none_or(|end| end > len) {
+            return Err(corrupt(format!(
+                "'{kind}' box runs past the end of the file"
+            )));
+        }
+
+        has_moov |= kind == "moov";
+        first.get_or_insert(kind);
+        offset += size;
+    }
+
+    match first.as_deref() {
+        Some("ftyp") if has_moov => Ok(()),
+        Some("ftyp") => Err(corrupt("no 'moov' box".to_owned())),
+        _ => Err(corrupt("doesn't start with an 'ftyp' box".to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    fn mp4_box(kind: &str, body: &[u8]) -> Vec<u8> {
+        let mut bytes = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        bytes.extend(kind.as_bytes());
+        bytes.extend(body);
+        bytes
+    }
+
+    /// Checks `bytes` as an MP4, returning the reason it's broken, if it is.
+    fn check(name: &str, bytes: &[u8]) -> Option<String> {
+        let path: PathBuf =
+            std::env::temp_dir().join(format!("echo360-{name}-{}.mp4", std::process::id()));
+        fs::write(&path, bytes).unwrap();
+        let result = check_mp4(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Ok(()) => None,
+            Err(Error::Corrupt { reason, .. }) => Some(reason),
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+
+    fn valid() -> Vec<u8> {
+        [
+            mp4_box("ftyp", b"isom\0\0\0\0"),
+            mp4_box("moov", &[0; 16]),
+            mp4_box("mdat", &[1; 32]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn accepts_well_formed_files() {
+        assert_eq!(check("valid", &valid()), None);
+    }
+
+    #[test]
+    fn rejects_truncated_boxes() {
+        let bytes = valid();
+        // Cut inside the last box's body, then inside its header
+        assert!(check("short-body", &bytes[..bytes.len() - 1])
+            .unwrap()
+            .contains("'mdat' box runs past"));
+        let header_cut = bytes.len() - 32 - 4;
+        assert!(check("short-header", &bytes[..header_cut])
+            .unwrap()
+            .contains("truncated box header"));
+        assert!(check("empty", &[]).is_some());
+    }
+
+    #[test]
+    fn needs_ftyp_and_moov() {
+        let no_moov = [mp4_box("ftyp", b"isom"), mp4_box("mdat", &[1; 8])].concat();
+        assert_eq!(check("no-moov", &no_moov).as_deref(), Some("no 'moov' box"));
+        let no_ftyp = [mp4_box("moov", &[0; 8]), mp4_box("ftyp", b"isom")].concat();
+        assert!(check("no-ftyp", &no_ftyp).unwrap().contains("'ftyp'"));
+    }
+
+    #[test]
+    fn reads_boxes_sized_zero_to_the_end() {
+        let mut bytes = [mp4_box("ftyp", b"isom"), mp4_box("moov", &[0; 8])].concat();
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(b"mdat");
+        bytes.extend([1; 100]);
+        assert_eq!(check("size-zero", &bytes), None);
+    }
+
+    #[test]
+    fn reads_64_bit_sizes() {
+        let large = |size: u64| {
+            let mut bytes = [mp4_box("ftyp", b"isom"), mp4_box("moov", &[0; 8])].concat();
+            bytes.extend(1u32.to_be_bytes());
+            bytes.extend(b"mdat");
+            bytes.extend(size.to_be_bytes());
+            bytes.extend([1; 16]);
+            bytes
+        };
+        assert_eq!(check("large", &large(32)), None);
+        assert!(check("large-past-end", &large(33))
+            .unwrap()
+            .contains("runs past"));
+        // Sizes that would overflow the offset
+        assert!(check("large-overflow", &large(u64::MAX)).is_some());
+        // The 64-bit size itself cut short
+        let mut cut = [mp4_box("ftyp", b"isom"), mp4_box("moov", &[0; 8])].concat();
+        cut.extend(1u32.to_be_bytes());
+        cut.extend(b"mdat");
+        cut.extend([0; 4]);
+        assert!(check("large-header-cut", &cut)
+            .unwrap()
+            .contains("truncated 'mdat' box header"));
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    courses::Section,
+    download::{Report, Source},
+    integrity,
+    sync::Manifest,
+    template::Template,
+    videos::Video,
+    Error, Result,
+};
+
+/// A recorded file that is missing or no longer matches its record.
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub lesson_id: String,
+    pub media_id: String,
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Every lesson downloaded into a folder, kept in the folder itself.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Library {
+    pub records: Vec<Record>,
+}
+
+/// The files saved for one media of a lesson.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Record {
+    pub lesson_id: String,
+    /// The lesson's name in the syllabus, empty in records made before it
+    /// was kept.
+    #[serde(default)]
+    pub lesson_name: String,
+    pub media_id: String,
+    pub section_id: String,
+    pub course_code: String,
+    pub files: Vec<FileRecord>,
+    /// RFC 3339 time of the download.
+    pub downloaded: String,
+    /// The file name template the files were named with.
+    pub template: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct FileRecord {
+    /// Relative to the library's folder.
+    pub path: PathBuf,
+    pub size: u64,
+    /// Hex SHA-256 of the contents.
+    pub sha256: String,
+}
+
+impl Library {
+    const FILE_NAME: &'static str = ".echo360-library.json";
+
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(Self::FILE_NAME)
+    }
+
+    /// Reads the library in `dir`, or an empty one if nothing was downloaded
+    /// there yet.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = Self::path(dir);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        serde_json::from_str(&contents).map_err(|source| Error::BadManifest { path, source })
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(Self::path(dir), serde_json::to_string_pretty(self).unwrap())?;
+        Ok(())
+    }
+
+    pub fn is_downloaded(&self, lesson_id: &str) -> bool {
+        self.records
+            .iter()
+            .any(|record| record.lesson_id == lesson_id)
+    }
+
+    /// Whether a lesson has a record in `dir` whose files are all there.
+    pub fn on_disk(&self, dir: &Path, lesson_id: &str) -> bool {
+        self.records
+            .iter()
+            .filter(|record| record.lesson_id == lesson_id)
+            .any(|record| {
+                record
+                    .files
+                    .iter()
+                    .all(|file| dir.join(&file.path).exists())
+            })
+    }
+
+    /// The name of the lesson `path`, in `dir`, was downloaded for, if
+    /// recorded.
+    pub fn lesson_name(&self, dir: &Path, path: &Path) -> Option<&str> {
+        let path = path.strip_prefix(dir).unwrap_or(path);
+        self.records
+            .iter()
+            .rev()
+            .filter(|record| !record.lesson_name.is_empty())
+            .find(|record| record.files.iter().any(|file| file.path == path))
+            .map(|record| record.lesson_name.as_str())
+    }
+
+    /// Whether `path`, in `dir`, is recorded as a file of a lesson other than
+    /// `lesson_id`.
+    pub fn belongs_elsewhere(&self, dir: &Path, lesson_id: &str, path: &Path) -> bool {
+        let path = path.strip_prefix(dir).unwrap_or(path);
+        self.records
+            .iter()
+            .filter(|record| record.lesson_id != lesson_id)
+            .any(|record| record.files.iter().any(|file| file.path == path))
+    }
+
+    /// The most recent record of a lesson.
+    pub fn lesson(&self, lesson_id: &str) -> Option<&Record> {
+        self.records
+            .iter()
+            .rev()
+            .find(|record| record.lesson_id == lesson_id)
+    }
+
+    /// Records the files a download saved into `dir` with the rest of their
+    /// media's files, in place of earlier records of the same paths.
+    pub fn add(
+        &mut self,
+        dir: &Path,
+        section: &Section,
+        videos: &[&Video],
+        template: &Template,
+        report: &Report,
+    ) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let mut downloaded: BTreeMap<(&str, &str), Vec<FileRecord>> = BTreeMap::new();
+        for outcome in report
+            .outcomes
+            .iter()
+            .filter(|outcome| outcome.result.is_ok())
+        {
+            let job = &outcome.job;
+            let paths = match &job.source {
+                Source::Captions(formats) => formats
+                    .iter()
+                    .map(|format| job.path.with_extension(format.extension()))
+                    .collect(),
+                _ => vec![job.path.clone()],
+            };
+            let files = downloaded
+                .entry((job.lesson_id.as_str(), job.media_id.as_str()))
+                .or_default();
+            // Captions the lesson turned out not to have left nothing behind
+            for path in paths.into_iter().filter(|path| path.exists()) {
+                files.push(FileRecord {
+                    size: fs::metadata(&path)?.len(),
+                    sha256: checksum(&path)?,
+                    path: path.strip_prefix(dir).unwrap_or(&path).to_owned(),
+                });
+            }
+        }
+
+        for ((lesson_id, media_id), files) in downloaded {
+            let existing = self
+                .records
+                .iter()
+                .position(|record| record.lesson_id == lesson_id && record.media_id == media_id);
+            let record = match existing {
+                Some(index) => &mut self.records[index],
+                None if files.is_empty() => continue,
+                None => {
+                    self.records.push(Record {
+                        lesson_id: lesson_id.to_owned(),
+                        lesson_name: String::new(),
+                        media_id: media_id.to_owned(),
+                        section_id: String::new(),
+                        course_code: String::new(),
+                        files: Vec::new(),
+                        downloaded: String::new(),
+                        template: String::new(),
+                    });
+                    self.records.last_mut().unwrap()
+                }
+            };
+            record
+                .files
+                .retain(|file| files.iter().all(|new| new.path != file.path));
+            record.files.extend(files);
+            if let Some(video) = videos.iter().find(|video| video.lesson.id == lesson_id) {
+                record.lesson_name = video.lesson.display_name.clone();
+            }
+            record.section_id = section.section_id.clone();
+            record.course_code = section.course_code.clone();
+            record.downloaded = now.clone();
+            record.template = template.to_string();
+        }
+        Ok(())
+    }
+
+    /// Re-checks every recorded file in `dir`: that it exists with the
+    /// recorded size and checksum, and that MP4s are well formed.
+    pub fn verify(&self, dir: &Path) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for record in &self.records {
+            for file in &record.files {
+                let path = dir.join(&file.path);
+                let reason = match check_file(&path, file) {
+                    Ok(()) => continue,
+                    Err(Error::Corrupt { reason, .. }) => reason,
+                    Err(err) => err.to_string(),
+                };
+                problems.push(Problem {
+                    lesson_id: record.lesson_id.clone(),
+                    media_id: record.media_id.clone(),
+                    path,
+                    reason,
+                });
+            }
+        }
+        problems
+    }
+
+    /// Deletes the broken files and forgets them, here and in the sync
+    /// manifest, so the next download or sync fetches them again. The intact
+    /// files of the same lessons stay.
+    pub fn discard(&mut self, dir: &Path, problems: &[Problem]) -> Result<()> {
+        for problem in problems {
+            match fs::remove_file(&problem.path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+            let path = problem.path.strip_prefix(dir).unwrap_or(&problem.path);
+            for record in &mut self.records {
+                if record.lesson_id == problem.lesson_id && record.media_id == problem.media_id {
+                    record.files.retain(|file| file.path != path);
+                }
+            }
+        }
+        self.records.retain(|record| !record.files.is_empty());
+
+        if !Manifest::path(dir).exists() {
+            return Ok(());
+        }
+        let mut manifest = Manifest::load(dir)?;
+        let lessons: HashSet<&str> = problems
+            .iter()
+            .map(|problem| problem.lesson_id.as_str())
+            .collect();
+        for lesson_id in lessons {
+            manifest.forget(lesson_id);
+        }
+        manifest.save(dir)
+    }
+
+    /// Discards `problems`, found by verifying the library in `dir`.
+    pub fn repair(dir: &Path, problems: &[Problem]) -> Result<()> {
+        let mut library = Self::load(dir)?;
+        library.discard(dir, problems)?;
+        library.save(dir)
+    }
+}
+
+fn check_file(path: &Path, file: &FileRecord) -> Result<()> {
+    let corrupt = |reason: String| Error::Corrupt {
+        path: path.to_owned(),
+        reason,
+    };
+    let size = match fs::metadata(path) {
+        Ok(meta) => meta.len(),
+        Err(err) if err.kind() == ErrorKind::NotFound => return Err(corrupt("missing".to_owned())),
+        Err(err) => return Err(err.into()),
+    };
+    if size != file.size {
+        return Err(corrupt(format!("{size} bytes, expected {}", file.size)));
+    }
+    if checksum(path)? != file.sha256 {
+        return Err(corrupt("checksum doesn't match".to_owned()));
+    }
+    if integrity::is_mp4(path) {
+        integrity::check_mp4(path)?;
+    }
+    Ok(())
+}
+
+fn checksum(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(dir: &Path, lesson_id: &str, files: &[(&str, &str)]) -> Record {
+        Record {
+            lesson_id: lesson_id.to_owned(),
+            lesson_name: String::new(),
+            media_id: "media".to_owned(),
+            section_id: String::new(),
+            course_code: String::new(),
+            files: files
+                .iter()
+                .map(|(name, contents)| {
+                    let path = dir.join(name);
+                    fs::write(&path, contents).unwrap();
+                    FileRecord {
+                        path: PathBuf::from(name),
+                        size: contents.len() as u64,
+                        sha256: checksum(&path).unwrap(),
+                    }
+                })
+                .collect(),
+            downloaded: String::new(),
+            template: String::new(),
+        }
+    }
+
+    #[test]
+    fn discards_only_broken_files() {
+        let dir = std::env::temp_dir().join(format!("echo360-library-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut library = Library {
+            records: vec![
+                record(&dir, "1", &[("one.ts", "video"), ("one.vtt", "WEBVTT\n")]),
+                record(&dir, "2", &[("two.ts", "video")]),
+            ],
+        };
+        fs::write(dir.join("one.ts"), "vid").unwrap();
+        fs::write(dir.join("two.ts"), "oops!").unwrap();
+
+        let problems = library.verify(&dir);
+        let broken: Vec<&Path> = problems
+            .iter()
+            .map(|problem| problem.path.as_path())
+            .collect();
+        assert_eq!(broken, vec![dir.join("one.ts"), dir.join("two.ts")]);
+        // Verifying alone deletes nothing
+        assert!(dir.join("one.ts").exists());
+
+        library.discard(&dir, &problems).unwrap();
+        assert!(!dir.join("one.ts").exists());
+        assert!(!dir.join("two.ts").exists());
+        assert!(dir.join("one.vtt").exists());
+        assert_eq!(library.records.len(), 1);
+        assert_eq!(library.records[0].files[0].path, Path::new("one.vtt"));
+        assert!(library.verify(&dir).is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,18 +1,51 @@
+pub mod captions;
 mod error;
-use std::{cell::{OnceCell, RefCell}, thread::sleep, time::Duration};
+use std::{
+    cell::{OnceCell, RefCell},
+    sync::atomic::{AtomicBool, Ordering},
+    thread::sleep,
+    time::Duration,
+};
 
-use error::Result;
+pub use error::{Error, Result};
+
+use captions::CaptionFormat;
 
 pub mod courses;
 use courses::Enrollments;
 
+pub mod download;
+use download::{DownloadOptions, ExistingFiles, Layout, Report};
+
+pub mod hls;
+
+pub mod integrity;
+
+pub mod library;
+
+pub mod session;
+use session::Session;
+
+pub mod sync;
+
+pub mod template;
+
+pub mod transcripts;
+
 pub mod videos;
 
 use dotenv::dotenv;
-use reqwest::{blocking::Client, header};
+use reqwest::{
+    blocking::{Client, Response},
+    header, StatusCode,
+};
+use serde::de::DeserializeOwned;
 use thirtyfour::{support::block_on, DesiredCapabilities, WebDriver};
 
-use self::{courses::Section, videos::VideoData};
+use self::{
+    courses::Section,
+    videos::{Quality, StreamRule, Video, VideoData},
+};
 
 #[derive(Default)]
 pub struct Echo360 {
@@ -22,55 +55,258 @@ pub struct Echo360 {
     pub selected: RefCell<Section>,
     pub videos: RefCell<Vec<VideoData>>,
     pub download_path: RefCell<String>,
+    pub template: RefCell<String>,
     pub captions: RefCell<bool>,
+    pub caption_formats: RefCell<Vec<CaptionFormat>>,
+    pub workers: RefCell<usize>,
+    pub existing: RefCell<ExistingFiles>,
+    pub quality: RefCell<Quality>,
+    pub streams: RefCell<StreamRule>,
+    pub layout: RefCell<Layout>,
 }
 
 impl Echo360 {
-    pub fn login() -> Result<Self> {
+    /// Download options from the settings edited in the GUI.
+    pub fn download_options(&self) -> Result<DownloadOptions> {
+        Ok(DownloadOptions {
+            path: self.download_path.borrow().as_str().into(),
+            template: self.template.borrow().parse()?,
+            captions: *self.captions.borrow(),
+            caption_formats: self.caption_formats.borrow().clone(),
+            workers: *self.workers.borrow(),
+            existing: *self.existing.borrow(),
+            quality: *self.quality.borrow(),
+            streams: *self.streams.borrow(),
+            layout: *self.layout.borrow(),
+        })
+    }
+
+    /// Authenticates with, in order: the `.env` cookie, the stored session from
+    /// a previous launch, or a browser login through WebDriver.
+    /// Logs in with the environment's cookie, the stored session or, failing
+    /// both, through the browser. Setting `cancel` abandons a browser login.
+    pub fn login(cancel: &AtomicBool) -> Result<Self> {
         dotenv().ok();
 
-        let (cookie, domain) = match (
+        if let (Ok(cookie), Ok(domain)) = (
             std::env::var("PLAY_SESSION_COOKIE"),
             std::env::var("DOMAIN"),
         ) {
-            (Ok(cookie), Ok(domain)) => (cookie, domain),
-            _ => {
-                // env variables aren't set, use browser to log in
-                let caps = DesiredCapabilities::chrome();
-                let driver = block_on(WebDriver::new("http://localhost:9515", caps))?;
-
-                block_on(driver.goto("https://login.echo360.org/login"))?;
-
-                let domain = loop {
-                    // Busy loop until logged in
-                    let url = block_on(driver.current_url())?;
-                    if let Some(domain) = url.domain() {
-                        if domain.starts_with("echo360") {
-                            break domain.to_owned();
-                        };
-                    }
-                    sleep(Duration::from_millis(1000));
-                };
+            return Self::with_session(&Session { cookie, domain });
+        }
+
+        if let Some(session) = Session::load() {
+            let echo360 = Self::with_session(&session)?;
+            if echo360.session_valid()? {
+                return Ok(echo360);
+            }
+            log::info!("stored session was rejected, logging in again");
+            Session::clear();
+        }
 
-                let cookie = block_on(driver.get_named_cookie("PLAY_SESSION"))?;
+        Self::with_session(&Self::browser_session(cancel)?)
+    }
 
-                block_on(driver.quit())?;
+    /// Runs `request`, logging in through the browser and retrying once if the
+    /// session has expired.
+    pub fn retry_expired<T>(&mut self, request: impl Fn(&Self) -> Result<T>) -> Result<T> {
+        match request(self) {
+            Err(Error::SessionExpired) => {
+                log::info!("session expired, logging in again");
+                let session = Self::browser_session(&AtomicBool::new(false))?;
+                self.use_session(&session)?;
+                request(self)
+            }
+            result => result,
+        }
+    }
+
+    /// Downloads `videos` out of the section's `syllabus`, logging in through
+    /// the browser and retrying the lessons that failed because the session
+    /// expired.
+    pub fn download_videos(
+        &mut self,
+        section: &Section,
+        syllabus: &[&Video],
+        videos: &[&Video],
+        options: &DownloadOptions,
+    ) -> Result<Report> {
+        let download = |echo360: &Self, videos: &[&Video]| {
+            Video::download_videos(
+                &echo360.client,
+                &echo360.domain,
+                section,
+                syllabus,
+                videos,
+                options,
+                None,
+            )
+        };
+        let mut report = self.retry_expired(|echo360| download(echo360, videos))?;
+        let expired = report.expired_lessons();
+        if expired.is_empty() {
+            return Ok(report);
+        }
+
+        log::info!("session expired during the download, logging in again");
+        let session = Self::browser_session(&AtomicBool::new(false))?;
+        self.use_session(&session)?;
+        let retry: Vec<&Video> = videos
+            .iter()
+            .copied()
+            .filter(|video| expired.contains(&video.lesson.id))
+            .collect();
+        report.replace(&expired, download(self, &retry)?);
+        Ok(report)
+    }
+
+    /// Logs in through the browser, replacing any stored session. Setting
+    /// `cancel` closes the browser and gives up.
+    pub fn browser_session(cancel: &AtomicBool) -> Result<Session> {
+        Session::clear();
+        let session = Self::browser_login(cancel)?;
+        if let Err(err) = session.save() {
+            log::warn!("could not save session: {err}");
+        }
+        Ok(session)
+    }
+
+    /// Switches to a fresh session, keeping the loaded courses and settings.
+    pub fn use_session(&mut self, session: &Session) -> Result<()> {
+        self.client = Self::client(session)?;
+        self.domain = session.domain.clone();
+        Ok(())
+    }
+
+    fn browser_login(cancel: &AtomicBool) -> Result<Session> {
+        let caps = DesiredCapabilities::chrome();
+        let driver = block_on(WebDriver::new("http://localhost:9515", caps))?;
 
-                dbg!(("PLAY_SESSION=".to_owned() + cookie.value.as_str(), "https://".to_owned() + domain.as_str()))
+        block_on(driver.goto("https://login.echo360.org/login"))?;
+
+        let domain = loop {
+            // Busy loop until logged in
+            if cancel.load(Ordering::Relaxed) {
+                block_on(driver.quit())?;
+                return Err(Error::LoginCancelled);
+            }
+            let url = block_on(driver.current_url())?;
+            if let Some(domain) = url.domain() {
+                if domain.starts_with("echo360") {
+                    break domain.to_owned();
+                };
             }
+            sleep(Duration::from_millis(1000));
+        };
+
+        let cookie = block_on(driver.get_named_cookie("PLAY_SESSION"))?;
+
+        block_on(driver.quit())?;
+
+        let session = Session {
+            cookie: "PLAY_SESSION=".to_owned() + cookie.value.as_str(),
+            domain: "https://".to_owned() + domain.as_str(),
         };
+        log::debug!("logged in to {}", session.domain);
+        Ok(session)
+    }
 
+    fn client(session: &Session) -> Result<Client> {
         let mut headers = header::HeaderMap::new();
-        headers.insert("Cookie", cookie.parse().unwrap());
+        headers.insert(
+            "Cookie",
+            session.cookie.parse().map_err(|_| Error::BadCookie)?,
+        );
 
-        let client = reqwest::blocking::Client::builder()
+        Ok(reqwest::blocking::Client::builder()
             .default_headers(headers)
-            .build()?;
+            .build()?)
+    }
 
+    fn with_session(session: &Session) -> Result<Self> {
         Ok(Self {
-            client,
-            domain,
+            client: Self::client(session)?,
+            domain: session.domain.clone(),
+            template: RefCell::new(template::Template::DEFAULT.to_owned()),
+            workers: RefCell::new(download::DEFAULT_WORKERS),
+            caption_formats: RefCell::new(vec![CaptionFormat::Vtt]),
             ..Default::default()
         })
     }
+
+    fn session_valid(&self) -> Result<bool> {
+        let response = self
+            .client
+            .get(&(self.domain.clone() + Enrollments::REQUEST_PATH))
+            .send()?;
+        match authenticated(response) {
+            Ok(response) => Ok(response.status().is_success()),
+            Err(Error::SessionExpired) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Rejects responses showing the session has expired: an auth error status, a
+/// redirect to the login host, or an HTML page where data was expected.
+fn authenticated(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status == StatusCode::UNAUTHORIZED
+        || status == StatusCode::FORBIDDEN
+        || on_login_page(&response)
+        || is_html(&response)
+    {
+        return Err(Error::SessionExpired);
+    }
+    Ok(response)
+}
+
+/// Whether the request was redirected to the login host.
+fn on_login_page(response: &Response) -> bool {
+    response
+        .url()
+        .host_str()
+        .is_some_and(|host| host.starts_with("login."))
+}
+
+fn is_html(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"))
+}
+
+/// Turns an error status into [`Error::Status`].
+fn check_status(response: Response) -> Result<Response> {
+    let status = response.status();
+    if status.is_client_error() || status.is_server_error() {
+        return Err(Error::Status {
+            url: response.url().to_string(),
+            status,
+        });
+    }
+    Ok(response)
+}
+
+/// Decodes a JSON API response, keeping the start of the body if it doesn't parse.
+fn decode<T: DeserializeOwned>(response: Response) -> Result<T> {
+    decode_raw(response).map(|(value, _)| value)
+}
+
+/// Like [`decode`], also handing back the body as received.
+fn decode_raw<T: DeserializeOwned>(response: Response) -> Result<(T, String)> {
+    const SNIPPET_LEN: usize = 200;
+
+    let response = check_status(authenticated(response)?)?;
+    let url = response.url().to_string();
+    let body = response.text()?;
+    match serde_json::from_str(&body) {
+        Ok(value) => Ok((value, body)),
+        Err(source) => Err(Error::Decode {
+            url,
+            source,
+            snippet: body.chars().take(SNIPPET_LEN).collect(),
+        }),
+    }
 }
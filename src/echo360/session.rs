@@ -0,0 +1,85 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Result;
+
+/// Cookie and domain captured by a browser login, kept between launches.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Session {
+    /// `PLAY_SESSION=...`, sent as the Cookie header.
+    pub cookie: String,
+    /// e.g. `https://echo360.net.au`
+    pub domain: String,
+}
+
+/// Leaves the cookie out, as it's as good as a password.
+impl core::fmt::Debug for Session {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Session")
+            .field("cookie", &"<hidden>")
+            .field("domain", &self.domain)
+            .finish()
+    }
+}
+
+impl Session {
+    const FILE_NAME: &'static str = "session.json";
+
+    /// `<config dir>/echo360-downloader/session.json`
+    pub fn path() -> Option<PathBuf> {
+        Some(
+            dirs::config_dir()?
+                .join(env!("CARGO_PKG_NAME"))
+                .join(Self::FILE_NAME),
+        )
+    }
+
+    pub fn load() -> Option<Self> {
+        let contents = fs::read_to_string(Self::path()?).ok()?;
+        match serde_json::from_str(&contents) {
+            Ok(session) => Some(session),
+            Err(err) => {
+                log::warn!("ignoring unreadable stored session: {err}");
+                None
+            }
+        }
+    }
+
+    /// Writes the session so only the current user can read it.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = Self::path() else {
+            log::warn!("no config directory, session not saved");
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+            options.mode(0o600);
+            // `mode` only applies to new files
+            if path.exists() {
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+            }
+        }
+
+        let mut file = options.open(&path)?;
+        file.write_all(serde_json::to_string_pretty(self).unwrap().as_bytes())?;
+        Ok(())
+    }
+
+    pub fn clear() {
+        if let Some(path) = Self::path() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
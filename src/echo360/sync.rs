@@ -0,0 +1,150 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use super::{download::Report, library::Library, videos::Video, Error, Result};
+
+/// What earlier syncs fetched into a download folder, per section.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Manifest {
+    /// Lessons by id, by section id.
+    sections: BTreeMap<String, BTreeMap<String, Entry>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Entry {
+    name: String,
+    has_content: bool,
+    /// Whether every file of the lesson is on disk.
+    downloaded: bool,
+    /// RFC 3339 time of the last sync that saw the lesson.
+    synced: String,
+}
+
+impl Manifest {
+    const FILE_NAME: &'static str = ".echo360-sync.json";
+
+    pub fn path(dir: &Path) -> PathBuf {
+        dir.join(Self::FILE_NAME)
+    }
+
+    /// Reads the manifest in `dir`, or an empty one if there isn't one yet.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = Self::path(dir);
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        serde_json::from_str(&contents).map_err(|source| Error::BadManifest { path, source })
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        fs::write(Self::path(dir), serde_json::to_string_pretty(self).unwrap())?;
+        Ok(())
+    }
+
+    /// Lessons with content that no earlier sync finished downloading: new
+    /// ones, ones that have gained content, and ones whose download failed.
+    pub fn pending<'a>(&self, section_id: &str, videos: &[&'a Video]) -> Vec<&'a Video> {
+        let lessons = self.sections.get(section_id);
+        videos
+            .iter()
+            .copied()
+            .filter(|video| video.has_content)
+            .filter(|video| {
+                !lessons
+                    .and_then(|lessons| lessons.get(&video.lesson.id))
+                    .is_some_and(|entry| entry.downloaded)
+            })
+            .collect()
+    }
+
+    /// Marks a lesson as not downloaded in every section, so the next sync
+    /// fetches it again.
+    pub fn forget(&mut self, lesson_id: &str) {
+        for lessons in self.sections.values_mut() {
+            if let Some(entry) = lessons.get_mut(lesson_id) {
+                entry.downloaded = false;
+            }
+        }
+    }
+
+    /// Notes the state of every lesson after downloading `synced` into `dir`.
+    /// A synced lesson counts as downloaded when none of its files failed and
+    /// it either had one downloaded or the library has all of its files, as
+    /// lessons already on disk get no jobs.
+    pub fn record(
+        &mut self,
+        dir: &Path,
+        section_id: &str,
+        videos: &[&Video],
+        synced: &[&Video],
+        report: &Report,
+    ) -> Result<()> {
+        let library = Library::load(dir)?;
+        let synced: HashSet<&str> = synced
+            .iter()
+            .map(|video| video.lesson.id.as_str())
+            .collect();
+        let succeeded: HashSet<&str> = report
+            .outcomes
+            .iter()
+            .filter(|outcome| outcome.result.is_ok())
+            .map(|outcome| outcome.job.lesson_id.as_str())
+            .collect();
+        let failed: HashSet<&str> = report
+            .failed()
+            .map(|outcome| outcome.job.lesson_id.as_str())
+            .collect();
+        let lessons = self.sections.entry(section_id.to_owned()).or_default();
+        let now = Utc::now().to_rfc3339();
+
+        for video in videos {
+            let id = video.lesson.id.as_str();
+            let fetched = succeeded.contains(id) || library.on_disk(dir, id);
+            let downloaded = lessons.get(id).is_some_and(|entry| entry.downloaded)
+                || (synced.contains(id) && !failed.contains(id) && fetched);
+            lessons.insert(
+                id.to_owned(),
+                Entry {
+                    name: video.lesson.display_name.clone(),
+                    has_content: video.has_content,
+                    downloaded,
+                    synced: now.clone(),
+                },
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Downloads, with `download`, the lessons of a section's `videos` that
+/// earlier syncs to `dir` haven't fetched and `keep` accepts, then updates the
+/// folder's manifest.
+pub fn sync<'a>(
+    dir: &Path,
+    section_id: &str,
+    videos: &[&'a Video],
+    keep: impl Fn(&Video) -> bool,
+    download: impl FnOnce(&[&'a Video]) -> Result<Report>,
+) -> Result<Report> {
+    let mut manifest = Manifest::load(dir)?;
+    let pending: Vec<&Video> = manifest
+        .pending(section_id, videos)
+        .into_iter()
+        .filter(|video| keep(video))
+        .collect();
+    log::info!("{} new lessons to sync to {}", pending.len(), dir.display());
+    let report = download(&pending)?;
+    manifest.record(dir, section_id, videos, &pending, &report)?;
+    manifest.save(dir)?;
+    Ok(report)
+}
@@ -0,0 +1,211 @@
+use std::str::FromStr;
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local,
+};
+
+use super::{courses::Section, videos::Video, Error, Result};
+
+/// A file name pattern such as `{course_code}_{date:%Y-%m-%d}`.
+///
+/// Placeholders are `{course_code}`, `{section_name}`, `{group}`,
+/// `{lesson_name}`, `{date}`, `{time}`, `{index}` and `{media_title}`. `date`
+/// and `time` take a strftime format after a colon, `index` takes a zero-padded
+/// width (`{index:02}`). Use `{{` and `}}` for literal braces.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Template {
+    source: String,
+    parts: Vec<Part>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Part {
+    Text(String),
+    CourseCode,
+    SectionName,
+    Group,
+    LessonName,
+    Date(String),
+    Time(String),
+    Index(usize),
+    MediaTitle,
+}
+
+/// Everything a template can refer to for one output file.
+pub struct Fields<'a> {
+    pub section: &'a Section,
+    pub video: &'a Video,
+    pub media_title: &'a str,
+}
+
+impl Template {
+    pub const DEFAULT: &'static str = "{course_code}_{date}";
+    /// Used for lessons without a start time when the template needs one.
+    const UNDATED: &'static str = "{lesson_name}";
+
+    pub fn parse(source: &str) -> Result<Self> {
+        let bad = |reason: &str| Error::Template(format!("{reason} in '{source}'"));
+
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '}' => return Err(bad("unmatched '}'")),
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(bad("unclosed '{'")),
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Self::placeholder(&placeholder).map_err(|reason| bad(&reason))?);
+                }
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+
+        Ok(Self {
+            source: source.to_owned(),
+            parts,
+        })
+    }
+
+    fn placeholder(placeholder: &str) -> core::result::Result<Part, String> {
+        let (name, spec) = match placeholder.split_once(':') {
+            Some((name, spec)) => (name, Some(spec)),
+            None => (placeholder, None),
+        };
+        let strftime = |default: &str| {
+            let format = spec.unwrap_or(default).to_owned();
+            if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+                return Err(format!("bad time format '{format}'"));
+            }
+            Ok(format)
+        };
+
+        let part = match name {
+            "course_code" => Part::CourseCode,
+            "section_name" => Part::SectionName,
+            "group" => Part::Group,
+            "lesson_name" => Part::LessonName,
+            "date" => Part::Date(strftime("%Y-%m-%d")?),
+            "time" => Part::Time(strftime("%H-%M")?),
+            "index" => Part::Index(match spec {
+                Some(width) => width
+                    .parse()
+                    .map_err(|_| format!("bad index width '{width}'"))?,
+                None => 0,
+            }),
+            "media_title" => Part::MediaTitle,
+            _ => return Err(format!("unknown placeholder '{{{placeholder}}}'")),
+        };
+        if spec.is_some() && !matches!(part, Part::Date(_) | Part::Time(_) | Part::Index(_)) {
+            return Err(format!("'{name}' takes no format"));
+        }
+        Ok(part)
+    }
+
+    pub fn uses_media_title(&self) -> bool {
+        self.parts.contains(&Part::MediaTitle)
+    }
+
+    /// Renders a file name, without extension, safe to use on any platform.
+    pub fn render(&self, fields: &Fields) -> Result<String> {
+        let start: Option<DateTime<Local>> = fields.video.start_time()?.map(DateTime::from);
+        let needs_start = self
+            .parts
+            .iter()
+            .any(|part| matches!(part, Part::Date(_) | Part::Time(_)));
+        if start.is_none() && needs_start {
+            return Self::parse(Self::UNDATED)?.render(fields);
+        }
+        Ok(self.render_with(fields, start))
+    }
+
+    fn render_with(&self, fields: &Fields, start: Option<DateTime<Local>>) -> String {
+        let mut name = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => name.push_str(text),
+                Part::CourseCode => name.push_str(&fields.section.course_code),
+                Part::SectionName => name.push_str(&fields.section.section_name),
+                Part::Group => name.push_str(
+                    fields
+                        .video
+                        .groups
+                        .last()
+                        .map(String::as_str)
+                        .unwrap_or_default(),
+                ),
+                Part::LessonName => name.push_str(&fields.video.lesson.display_name),
+                Part::Date(format) | Part::Time(format) => {
+                    if let Some(start) = start {
+                        name.push_str(&start.format(format).to_string());
+                    }
+                }
+                Part::Index(width) => {
+                    name.push_str(&format!("{:0width$}", fields.video.index, width = *width))
+                }
+                Part::MediaTitle => name.push_str(fields.media_title),
+            }
+        }
+        sanitize(&name)
+    }
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Self::parse(Self::DEFAULT).unwrap()
+    }
+}
+
+impl core::fmt::Display for Template {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for Template {
+    type Err = Error;
+
+    fn from_str(source: &str) -> Result<Self> {
+        Self::parse(source)
+    }
+}
+
+/// Replaces characters that aren't allowed in file names on common platforms.
+pub fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "_".to_owned()
+    } else {
+        name.to_owned()
+    }
+}
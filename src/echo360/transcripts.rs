@@ -0,0 +1,217 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+
+use super::{
+    captions::{self, Cue},
+    library::Library,
+    Result,
+};
+
+/// Extensions a transcript's recording may have, in order of preference.
+const VIDEO_EXTENSIONS: [&str; 3] = ["mp4", "ts", "mp3"];
+
+/// The captions of one downloaded lesson.
+#[derive(Debug)]
+pub struct Transcript {
+    /// The lesson's name as recorded in the library, or failing that the
+    /// file name without extension.
+    pub lesson: String,
+    pub path: PathBuf,
+    /// The recording saved alongside, if any.
+    pub video: Option<PathBuf>,
+    pub cues: Vec<Cue>,
+}
+
+/// A cue matching every word of a search.
+pub struct Hit<'a> {
+    pub transcript: &'a Transcript,
+    pub cue: &'a Cue,
+}
+
+/// Every `.vtt` file under a download directory, read into memory.
+#[derive(Debug, Default)]
+pub struct Index {
+    pub transcripts: Vec<Transcript>,
+}
+
+impl Index {
+    /// Reads the captions under `dir` and its subdirectories, skipping any
+    /// that don't parse.
+    pub fn build(dir: &Path) -> Result<Self> {
+        let mut paths = Vec::new();
+        find_captions(dir, &mut paths)?;
+        paths.sort();
+        let library = Library::load(dir).unwrap_or_else(|err| {
+            log::warn!("showing file names, as the library can't be read: {err}");
+            Library::default()
+        });
+
+        let mut transcripts = Vec::new();
+        for path in paths {
+            let cues = match captions::parse_vtt(&fs::read_to_string(&path)?) {
+                Ok(cues) => cues,
+                Err(err) => {
+                    log::warn!("skipping {}: {err}", path.display());
+                    continue;
+                }
+            };
+            let video = VIDEO_EXTENSIONS
+                .iter()
+                .map(|extension| path.with_extension(extension))
+                .find(|video| video.exists());
+            let lesson = match library.lesson_name(dir, &path) {
+                Some(name) => name.to_owned(),
+                None => path
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            };
+            transcripts.push(Transcript {
+                lesson,
+                path,
+                video,
+                cues,
+            });
+        }
+        log::debug!(
+            "indexed {} transcripts in {}",
+            transcripts.len(),
+            dir.display()
+        );
+        Ok(Self { transcripts })
+    }
+
+    /// Cues containing every word of `query`, ignoring case, in lesson order.
+    pub fn search(&self, query: &str) -> Vec<Hit<'_>> {
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if words.is_empty() {
+            return Vec::new();
+        }
+        self.transcripts
+            .iter()
+            .flat_map(|transcript| {
+                transcript
+                    .cues
+                    .iter()
+                    .map(move |cue| Hit { transcript, cue })
+            })
+            .filter(|hit| {
+                let text = hit.cue.text.to_lowercase();
+                words.iter().all(|word| text.contains(word))
+            })
+            .collect()
+    }
+}
+
+/// Opens `video` in a player, starting `offset` in.
+///
+/// Uses the `VIDEO_PLAYER` command when set, where `{file}` and `{start}`
+/// (seconds) are filled in, then VLC or mpv. Falls back to the system's
+/// default player, which starts from the beginning.
+pub fn play(video: &Path, offset: Duration) -> Result<()> {
+    let start = offset.as_secs().to_string();
+    let file = video.to_string_lossy();
+
+    let player = std::env::var("VIDEO_PLAYER").ok();
+    if let Some(player) = player.filter(|player| !player.trim().is_empty()) {
+        let mut args: Vec<String> = player
+            .split_whitespace()
+            .map(|arg| arg.replace("{file}", &file).replace("{start}", &start))
+            .collect();
+        if !player.contains("{file}") {
+            args.push(file.into_owned());
+        }
+        Command::new(&args[0]).args(&args[1..]).spawn()?;
+        return Ok(());
+    }
+
+    let players = [
+        ("vlc", format!("--start-time={start}")),
+        ("mpv", format!("--start={start}")),
+    ];
+    for (player, seek) in players {
+        match Command::new(player).arg(seek).arg(video).spawn() {
+            Ok(_) => return Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    log::info!("no VLC or mpv found, opening from the start");
+    let opener = if cfg!(target_os = "windows") {
+        Command::new("cmd")
+            .args(["/C", "start", ""])
+            .arg(video)
+            .spawn()
+    } else if cfg!(target_os = "macos") {
+        Command::new("open").arg(video).spawn()
+    } else {
+        Command::new("xdg-open").arg(video).spawn()
+    };
+    opener?;
+    Ok(())
+}
+
+fn find_captions(dir: &Path, paths: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_captions(&path, paths)?;
+        } else if path.extension() == Some(OsStr::new("vtt")) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::echo360::library::{FileRecord, Record};
+
+    #[test]
+    fn names_lessons_from_the_library() {
+        let dir = std::env::temp_dir().join(format!("echo360-transcripts-{}", std::process::id()));
+        fs::create_dir_all(dir.join("COMP1234")).unwrap();
+        let vtt = "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nEigenvalues\n";
+        for name in ["COMP1234/COMP1234_2024-08-05.vtt", "notes.vtt"] {
+            fs::write(dir.join(name), vtt).unwrap();
+        }
+        let record = Record {
+            lesson_id: "lesson".to_owned(),
+            lesson_name: "Week 2: Linear Algebra".to_owned(),
+            media_id: "media".to_owned(),
+            section_id: "section".to_owned(),
+            course_code: "COMP1234".to_owned(),
+            files: vec![FileRecord {
+                path: PathBuf::from("COMP1234/COMP1234_2024-08-05.vtt"),
+                size: vtt.len() as u64,
+                sha256: String::new(),
+            }],
+            downloaded: String::new(),
+            template: String::new(),
+        };
+        Library {
+            records: vec![record],
+        }
+        .save(&dir)
+        .unwrap();
+
+        let index = Index::build(&dir).unwrap();
+        let mut lessons: Vec<&str> = index
+            .search("eigenvalues")
+            .iter()
+            .map(|hit| hit.transcript.lesson.as_str())
+            .collect();
+        lessons.sort();
+        // Files the library doesn't know keep their own name
+        assert_eq!(lessons, vec!["Week 2: Linear Algebra", "notes"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,12 +1,31 @@
-use std::fs::File;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    time::Duration,
+};
 
-use chrono::DateTime;
-use reqwest::blocking::Client;
+use chrono::{DateTime, FixedOffset, Local};
+use clap::ValueEnum;
+use reqwest::{blocking::Client, StatusCode, Url};
 use serde::Deserialize;
 
-use super::{courses::Section, Result};
+use super::{
+    authenticated, check_status,
+    courses::Section,
+    decode,
+    download::{
+        DownloadOptions, Downloader, Event, ExistingFiles, Job, Outcome, Plan, Report, Source,
+    },
+    hls::{HlsClient, MediaPlaylist},
+    is_html,
+    library::Library,
+    on_login_page,
+    template::{sanitize, Fields},
+    Error, Result,
+};
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Video {
     pub lesson: LessonData,
@@ -18,29 +37,310 @@ pub struct Video {
     pub end_time_utc: Option<String>,
     #[serde(skip)]
     pub download: bool,
+    /// Names of the syllabus groups containing this lesson, outermost first.
+    #[serde(skip)]
+    pub groups: Vec<String>,
+    /// Position in the syllabus, counting from 1.
+    #[serde(skip)]
+    pub index: usize,
+    /// Ids of the medias picked for this lesson, `None` to follow the
+    /// [`StreamRule`].
+    #[serde(skip)]
+    pub streams: Option<Vec<String>>,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LessonData {
-    id: String,
+    pub id: String,
     pub display_name: String,
 }
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Media {
-    id: String,
-    title: String,
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub media_type: MediaType,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum MediaType {
+    Video,
+    Audio,
+    #[default]
+    #[serde(other)]
+    Other,
+}
+
+/// What a media stream shows, as far as its type and title tell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamKind {
+    Screen,
+    Camera,
+    DocumentCamera,
+    Audio,
+    Other,
+}
+
+impl StreamKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Screen => "Screen",
+            Self::Camera => "Camera",
+            Self::DocumentCamera => "Document camera",
+            Self::Audio => "Audio",
+            Self::Other => "Stream",
+        }
+    }
+}
+
+/// Which streams to download for lessons without a choice of their own.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum StreamRule {
+    /// Only the first stream
+    #[default]
+    First,
+    /// Every stream
+    All,
+    /// Screen captures and audio, or the first stream if there are none
+    ScreenAndAudio,
+}
+
+impl StreamRule {
+    pub const ALL: [Self; 3] = [Self::First, Self::All, Self::ScreenAndAudio];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::First => "First stream",
+            Self::All => "All streams",
+            Self::ScreenAndAudio => "Screen + audio",
+        }
+    }
+}
+
+/// A rendition Echo360 can serve for a media.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, ValueEnum)]
+pub enum Quality {
+    /// Audio only
+    Audio,
+    /// Standard definition
+    Sd,
+    /// High definition
+    #[default]
+    Hd1,
+    /// Highest definition
+    Hd2,
+}
+
+impl Quality {
+    pub const ALL: [Self; 4] = [Self::Audio, Self::Sd, Self::Hd1, Self::Hd2];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Audio => "Audio",
+            Self::Sd => "SD",
+            Self::Hd1 => "HD",
+            Self::Hd2 => "HD2",
+        }
+    }
+
+    fn file_name(self) -> &'static str {
+        match self {
+            Self::Audio => "audio.mp3",
+            Self::Sd => "sd1.mp4",
+            Self::Hd1 => "hd1.mp4",
+            Self::Hd2 => "hd2.mp4",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Audio => "mp3",
+            _ => "mp4",
+        }
+    }
+
+    /// Picture height to aim for when choosing between HLS variants.
+    pub fn height(self) -> Option<u32> {
+        match self {
+            Self::Audio => None,
+            Self::Sd => Some(480),
+            Self::Hd1 => Some(720),
+            Self::Hd2 => Some(1080),
+        }
+    }
+
+    /// A typical bitrate in bytes per second, for estimating download sizes.
+    pub fn byte_rate(self) -> u64 {
+        match self {
+            Self::Audio => 16_000,
+            Self::Sd => 125_000,
+            Self::Hd1 => 300_000,
+            Self::Hd2 => 600_000,
+        }
+    }
+
+    /// The order to try renditions in: this one, then lower video qualities,
+    /// then higher ones, with audio only as the last resort.
+    pub fn fallbacks(self) -> Vec<Self> {
+        let videos = [Self::Sd, Self::Hd1, Self::Hd2];
+        let mut order = vec![self];
+        order.extend(videos.iter().rev().filter(|quality| **quality < self));
+        order.extend(videos.iter().filter(|quality| **quality > self));
+        if self != Self::Audio {
+            order.push(Self::Audio);
+        }
+        order
+    }
+}
+
+impl Media {
+    pub fn kind(&self) -> StreamKind {
+        if self.media_type == MediaType::Audio {
+            return StreamKind::Audio;
+        }
+        let title = self.title.to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|word| title.contains(word));
+        if mentions(&["document", "doc cam", "visualiser", "visualizer"]) {
+            StreamKind::DocumentCamera
+        } else if mentions(&["screen", "display", "computer", "desktop", "slides"]) {
+            StreamKind::Screen
+        } else if mentions(&["camera", "presenter", "video"]) {
+            StreamKind::Camera
+        } else if mentions(&["audio"]) {
+            StreamKind::Audio
+        } else {
+            StreamKind::Other
+        }
+    }
+
+    /// The title, or what kind of stream it is when untitled.
+    pub fn name(&self) -> &str {
+        if self.title.trim().is_empty() {
+            self.kind().label()
+        } else {
+            &self.title
+        }
+    }
+
+    pub fn url(&self, domain: &str, quality: Quality) -> String {
+        format!(
+            "{domain}/media/download/{}/{}",
+            self.id,
+            quality.file_name()
+        )
+    }
+
+    fn has_rendition(&self, client: &Client, domain: &str, quality: Quality) -> Result<bool> {
+        let response = client.head(&self.url(domain, quality)).send()?;
+        if on_login_page(&response) {
+            return Err(Error::SessionExpired);
+        }
+        // Where downloads are turned off the server refuses, or serves a page
+        // in place of the file. The HLS fallback notices a real expiry.
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::FORBIDDEN | StatusCode::METHOD_NOT_ALLOWED => {
+                Ok(false)
+            }
+            _ if is_html(&response) => Ok(false),
+            status if status.is_success() => Ok(true),
+            _ => check_status(authenticated(response)?).map(|_| false),
+        }
+    }
+
+    /// Every rendition the server has for this media, lowest first.
+    pub fn renditions(&self, client: &Client, domain: &str) -> Result<Vec<Quality>> {
+        let mut available = Vec::new();
+        for quality in Quality::ALL {
+            if self.has_rendition(client, domain, quality)? {
+                available.push(quality);
+            }
+        }
+        Ok(available)
+    }
+
+    /// The HLS playlist the player streams this media from, for servers that
+    /// don't offer direct downloads.
+    pub fn hls_playlist(&self, client: &Client, domain: &str, lesson: &str) -> Result<Option<Url>> {
+        let response = client
+            .get(&format!(
+                "{domain}/api/ui/echoplayer/lessons/{lesson}/medias/{}/player-properties",
+                self.id
+            ))
+            .send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let properties: PlayerProperties = decode(response)?;
+        Ok(properties.playlist())
+    }
+
+    /// The first available rendition in `preferred`'s fallback order.
+    pub fn best_rendition(
+        &self,
+        client: &Client,
+        domain: &str,
+        preferred: Quality,
+    ) -> Result<Option<Quality>> {
+        for quality in preferred.fallbacks() {
+            if self.has_rendition(client, domain, quality)? {
+                return Ok(Some(quality));
+            }
+        }
+        Ok(None)
+    }
 }
 
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GroupInfo {
-    group_id: String,
+    // group_id: String,
     pub name: String,
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayerProperties {
+    data: PlayerData,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayerData {
+    #[serde(default)]
+    playable_audio_video: PlayableAudioVideo,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayableAudioVideo {
+    #[serde(default)]
+    playable_medias: Vec<PlayableMedia>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlayableMedia {
+    uri: String,
+    #[serde(default)]
+    is_hls: bool,
+}
+
+impl PlayerProperties {
+    /// The first HLS stream the player lists.
+    fn playlist(&self) -> Option<Url> {
+        self.data
+            .playable_audio_video
+            .playable_medias
+            .iter()
+            .find(|media| media.is_hls)
+            .and_then(|media| Url::parse(&media.uri).ok())
+    }
+}
+
 #[derive(Debug, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct VideosResponse {
@@ -62,64 +362,581 @@ pub enum VideoData {
     },
 }
 
+impl VideoData {
+    /// Collects every lesson in the syllabus, descending into groups.
+    pub fn flatten(data: &[VideoData]) -> Vec<&Video> {
+        let mut lessons = Vec::new();
+        for video_data in data {
+            match video_data {
+                VideoData::SyllabusLessonType { lesson } => lessons.push(lesson),
+                VideoData::SyllabusGroupType { lessons: group, .. } => {
+                    lessons.extend(Self::flatten(group))
+                }
+            }
+        }
+        lessons
+    }
+
+    /// Like [`VideoData::flatten`], for changing the lessons.
+    pub fn flatten_mut(data: &mut [VideoData]) -> Vec<&mut Video> {
+        let mut lessons = Vec::new();
+        for video_data in data {
+            match video_data {
+                VideoData::SyllabusLessonType { lesson } => lessons.push(lesson),
+                VideoData::SyllabusGroupType { lessons: group, .. } => {
+                    lessons.extend(Self::flatten_mut(group))
+                }
+            }
+        }
+        lessons
+    }
+
+    /// Fills in each lesson's syllabus position and enclosing groups.
+    fn annotate(data: &mut [VideoData], groups: &mut Vec<String>, index: &mut usize) {
+        for video_data in data {
+            match video_data {
+                VideoData::SyllabusLessonType { lesson } => {
+                    *index += 1;
+                    lesson.index = *index;
+                    lesson.groups = groups.clone();
+                }
+                VideoData::SyllabusGroupType {
+                    group_info,
+                    lessons,
+                } => {
+                    groups.push(group_info.name.clone());
+                    Self::annotate(lessons, groups, index);
+                    groups.pop();
+                }
+            }
+        }
+    }
+}
+
 impl Video {
+    /// How long the recording runs, if both its times are known.
+    pub fn duration(&self) -> Option<Duration> {
+        let parse = |time: &Option<String>| DateTime::parse_from_rfc3339(time.as_deref()?).ok();
+        (parse(&self.end_time_utc)? - parse(&self.start_time_utc)?)
+            .to_std()
+            .ok()
+    }
+
+    /// The medias to download, from the lesson's own choice or else `rule`.
+    pub fn selected_medias(&self, rule: StreamRule) -> Vec<&Media> {
+        if let Some(streams) = &self.streams {
+            return self
+                .medias
+                .iter()
+                .filter(|media| streams.contains(&media.id))
+                .collect();
+        }
+        match rule {
+            StreamRule::First => self.medias.first().into_iter().collect(),
+            StreamRule::All => self.medias.iter().collect(),
+            StreamRule::ScreenAndAudio => {
+                let picked: Vec<&Media> = self
+                    .medias
+                    .iter()
+                    .filter(|media| matches!(media.kind(), StreamKind::Screen | StreamKind::Audio))
+                    .collect();
+                if picked.is_empty() {
+                    self.medias.first().into_iter().collect()
+                } else {
+                    picked
+                }
+            }
+        }
+    }
+
+    pub fn start_time(&self) -> Result<Option<DateTime<FixedOffset>>> {
+        self.start_time_utc
+            .as_ref()
+            .map(|time| {
+                DateTime::parse_from_rfc3339(time).map_err(|source| Error::BadTimestamp {
+                    value: time.clone(),
+                    source,
+                })
+            })
+            .transpose()
+    }
+
     pub fn get_videos(
         client: &Client,
         domain: impl Into<String>,
         section_id: &String,
     ) -> Result<Vec<VideoData>> {
-        let VideosResponse { data, .. } = client
+        let response = client
             .get(&format!(
                 "{}/section/{}/syllabus",
                 domain.into(),
                 section_id
             ))
-            .send()
-            .unwrap()
-            .json::<VideosResponse>()
-            .unwrap();
+            .send()?;
+        let VideosResponse { mut data, .. } = decode(response)?;
+        VideoData::annotate(&mut data, &mut Vec::new(), &mut 0);
 
-        Ok(dbg!(data))
+        log::debug!("{data:?}");
+        Ok(data)
     }
 
+    /// Downloads the lessons, out of the section's whole `syllabus`, sending
+    /// progress to `events` if given, and records the saved files in the
+    /// folder's [`Library`].
     pub fn download_videos(
         client: &Client,
         domain: impl Into<String>,
-        code: &str,
-        videos: &Vec<&Video>,
-        path: impl Into<String>,
-        captions: bool,
-    ) -> Result<()> {
+        section: &Section,
+        syllabus: &[&Video],
+        videos: &[&Video],
+        options: &DownloadOptions,
+        events: Option<Sender<Event>>,
+    ) -> Result<Report> {
         let domain: String = domain.into();
-        let path: String = path.into();
-        for video in videos {
-            let start = &video.start_time_utc;
-            let name = match start {
-                Some(time) => {
-                    let date = DateTime::parse_from_rfc3339(&time).unwrap();
-                    format!("{}{}_{}", &path, code, date.format("%Y-%m-%d"))
-                }
-                None => format!("{}{}", &path, video.lesson.display_name),
+        let plan = Self::plan_downloads(client, &domain, section, syllabus, videos, options)?;
+        let mut downloader = Downloader::new(client, &domain, options.workers);
+        if let Some(events) = events {
+            downloader = downloader.with_progress(events);
+        }
+        let report = downloader.run(plan);
+
+        let mut library = Library::load(&options.path)?;
+        library.add(&options.path, section, videos, &options.template, &report)?;
+        library.save(&options.path)?;
+        Ok(report)
+    }
+
+    /// Works out the files to fetch for each lesson, without fetching them.
+    /// Asks the server which renditions exist to pick the preferred quality.
+    /// A lesson that can't be planned fails on its own, leaving the rest.
+    pub fn plan_downloads(
+        client: &Client,
+        domain: impl Into<String>,
+        section: &Section,
+        syllabus: &[&Video],
+        videos: &[&Video],
+        options: &DownloadOptions,
+    ) -> Result<Plan> {
+        let domain: String = domain.into();
+        let hls = HlsClient::new(client, &domain);
+        let mut plan = Plan::default();
+        // Stands in for the files of an output that couldn't be planned
+        let unplanned = |video: &Video, media: Option<&Media>, path: PathBuf, err: Error| {
+            log::warn!("can't download {}: {err}", video.lesson.display_name);
+            Outcome {
+                job: Job {
+                    lesson_id: video.lesson.id.clone(),
+                    media_id: media.map(|media| media.id.clone()).unwrap_or_default(),
+                    url: String::new(),
+                    source: Source::File,
+                    path,
+                    compare_size: false,
+                },
+                result: Err(err),
+            }
+        };
+
+        // Every lesson of the syllabus is named, so a lesson's name doesn't
+        // depend on which others are downloaded with it
+        let chosen: HashMap<&str, &Video> = videos
+            .iter()
+            .map(|video| (video.lesson.id.as_str(), *video))
+            .collect();
+        let mut lessons: Vec<&Video> = syllabus
+            .iter()
+            .map(|video| chosen.get(video.lesson.id.as_str()).unwrap_or(video))
+            .copied()
+            .collect();
+        lessons.extend(videos.iter().copied().filter(|video| {
+            !syllabus
+                .iter()
+                .any(|other| other.lesson.id == video.lesson.id)
+        }));
+
+        // One output per selected stream of each lesson
+        let mut outputs = Vec::new();
+        let mut names = Vec::new();
+        for video in lessons {
+            let wanted = chosen.contains_key(video.lesson.id.as_str());
+            let medias = video.selected_medias(options.streams);
+            let several = medias.len() > 1;
+            // Lessons not recorded yet take a name too, for when they are
+            let medias: Vec<Option<&Media>> = match medias.is_empty() {
+                true => vec![None],
+                false => medias.into_iter().map(Some).collect(),
             };
+            for (stream, media) in medias.into_iter().enumerate() {
+                let title = media.map_or("", Media::name);
+                let fields = Fields {
+                    section,
+                    video,
+                    media_title: title,
+                };
+                let mut name = match options.template.render(&fields) {
+                    Ok(name) => name,
+                    Err(err) if wanted => {
+                        let folder = options.path.join(options.layout.folder(section, video));
+                        let path = folder.join(sanitize(&video.lesson.display_name));
+                        plan.failed.push(unplanned(video, media, path, err));
+                        continue;
+                    }
+                    // A lesson that can't be named takes no name from others
+                    Err(_) => continue,
+                };
+                if several && !options.template.uses_media_title() {
+                    name = format!("{name}_{}", sanitize(title));
+                }
+                names.push(name);
+                // Captions come with the first stream
+                outputs.push((video, media, stream == 0, wanted));
+            }
+        }
+        let folders: Vec<PathBuf> = outputs
+            .iter()
+            .map(|(video, ..)| options.path.join(options.layout.folder(section, video)))
+            .collect();
 
-            let mut video_file = File::create(name.clone() + ".mp4")?;
-            let id = &video.medias.get(0).unwrap().id;
-            client
-                .get(&format!("{}/media/download/{}/hd1.mp4", &domain, id))
-                .send()?
-                .copy_to(&mut video_file)?;
-
-            if captions {
-                let mut caption_file = File::create(name + ".vtt")?;
-                client
-                    .get(&format!(
-                        "{}/api/ui/echoplayer/lessons/{}/medias/{}/transcript-file?format=vtt",
-                        &domain, video.lesson.id, id
+        // Names only need to be unique within their folder
+        let mut by_folder: BTreeMap<&Path, Vec<usize>> = BTreeMap::new();
+        for (i, folder) in folders.iter().enumerate() {
+            by_folder.entry(folder).or_default().push(i);
+        }
+        for indices in by_folder.values() {
+            let mut folder_names: Vec<String> = indices.iter().map(|&i| names[i].clone()).collect();
+            let folder_videos: Vec<&Video> = indices.iter().map(|&i| outputs[i].0).collect();
+            disambiguate(&mut folder_names, &folder_videos);
+            for (&i, name) in indices.iter().zip(folder_names) {
+                names[i] = name;
+            }
+        }
+
+        let library = Library::load(&options.path)?;
+        for (((video, media, primary, wanted), name), folder) in
+            outputs.into_iter().zip(names).zip(folders)
+        {
+            if !wanted {
+                continue;
+            }
+            let Some(media) = media else {
+                let err = Error::NoMedia {
+                    lesson: video.lesson.display_name.clone(),
+                };
+                plan.failed
+                    .push(unplanned(video, None, folder.join(&name), err));
+                continue;
+            };
+            let source = (|| match media.best_rendition(client, &domain, options.quality)? {
+                Some(quality) => {
+                    if quality != options.quality {
+                        log::info!(
+                            "{} has no {} rendition, using {}",
+                            video.lesson.display_name,
+                            options.quality.label(),
+                            quality.label()
+                        );
+                    }
+                    Ok((
+                        Source::File,
+                        media.url(&domain, quality),
+                        quality.extension(),
                     ))
-                    .send()?
-                    .copy_to(&mut caption_file)?;
+                }
+                None => {
+                    let playlist = media
+                        .hls_playlist(client, &domain, &video.lesson.id)?
+                        .ok_or_else(|| Error::NoMedia {
+                            lesson: video.lesson.display_name.clone(),
+                        })?;
+                    log::info!(
+                        "{} has no downloads, using its HLS stream",
+                        video.lesson.display_name
+                    );
+                    let media_playlist = MediaPlaylist::resolve(&hls, &playlist, options.quality)?;
+                    let extension = media_playlist.extension();
+                    Ok((Source::Hls(media_playlist), playlist.to_string(), extension))
+                }
+            })();
+            let (source, url, extension) = match source {
+                Ok(source) => source,
+                Err(err) => {
+                    plan.failed
+                        .push(unplanned(video, Some(media), folder.join(&name), err));
+                    continue;
+                }
+            };
+            // Each file with the extensions it's saved under
+            let mut files = vec![(source, url, vec![extension])];
+            if options.captions && primary && !options.caption_formats.is_empty() {
+                files.push((
+                    Source::Captions(options.caption_formats.clone()),
+                    format!(
+                        "{}/api/ui/echoplayer/lessons/{}/medias/{}/transcript-file?format=vtt",
+                        &domain, video.lesson.id, media.id
+                    ),
+                    options
+                        .caption_formats
+                        .iter()
+                        .map(|format| format.extension())
+                        .collect(),
+                ));
+            }
+
+            let extensions: Vec<&str> = files
+                .iter()
+                .flat_map(|(.., extensions)| extensions.clone())
+                .collect();
+            let name = output_name(options, &library, &folder, name, video, &extensions);
+
+            for (source, url, extensions) in files {
+                let paths: Vec<PathBuf> = extensions
+                    .iter()
+                    .map(|extension| folder.join(format!("{name}.{extension}")))
+                    .collect();
+                if options.existing == ExistingFiles::Skip && paths.iter().all(|path| path.exists())
+                {
+                    log::info!("skipping existing {}", paths[0].display());
+                    continue;
+                }
+                let path = paths[0].clone();
+                plan.jobs.push(Job {
+                    lesson_id: video.lesson.id.clone(),
+                    media_id: media.id.clone(),
+                    url,
+                    source,
+                    path,
+                    compare_size: options.existing == ExistingFiles::CompareSize,
+                });
             }
         }
-        Ok(())
+
+        Ok(plan)
+    }
+}
+
+/// The name to save a lesson's files under in `folder`, given the files with
+/// `extensions` there already. Renaming looks for a name with none of them on
+/// disk. The other policies act on whatever is there, unless the library
+/// records it as another lesson's, which is never replaced or taken as
+/// downloaded.
+fn output_name(
+    options: &DownloadOptions,
+    library: &Library,
+    folder: &Path,
+    name: String,
+    video: &Video,
+    extensions: &[&str],
+) -> String {
+    if options.existing == ExistingFiles::Rename {
+        return free_name(folder, &name, extensions, |_| false);
+    }
+    let free = free_name(folder, &name, extensions, |path| {
+        !library.belongs_elsewhere(&options.path, &video.lesson.id, path)
+    });
+    if free != name {
+        log::warn!(
+            "{} belongs to another lesson, saving {} as {free}",
+            folder.join(&name).display(),
+            video.lesson.display_name
+        );
+    }
+    free
+}
+
+/// Makes every name unique, so no lesson overwrites another in the same
+/// download. Clashing lessons get their start time added, or failing that their
+/// syllabus position, then a counter as a last resort.
+fn disambiguate(names: &mut [String], videos: &[&Video]) {
+    let clashing = |names: &[String]| -> Vec<usize> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for name in names {
+            *counts.entry(name.to_lowercase()).or_default() += 1;
+        }
+        (0..names.len())
+            .filter(|&i| counts[&names[i].to_lowercase()] > 1)
+            .collect()
+    };
+
+    let clashes = clashing(names);
+    if clashes.is_empty() {
+        return;
+    }
+    log::info!("{} lessons share a file name, renaming them", clashes.len());
+
+    let times: Vec<Option<String>> = clashes
+        .iter()
+        .map(|&i| {
+            let start = videos[i].start_time().ok().flatten()?;
+            Some(DateTime::<Local>::from(start).format("%H-%M").to_string())
+        })
+        .collect();
+    let mut with_times = names.to_vec();
+    for (&i, time) in clashes.iter().zip(&times) {
+        if let Some(time) = time {
+            with_times[i] = format!("{}_{time}", names[i]);
+        }
+    }
+    let still_clashing = clashing(&with_times);
+    for &i in &clashes {
+        names[i] = if still_clashing.contains(&i) {
+            format!("{}_{}", names[i], videos[i].index)
+        } else {
+            with_times[i].clone()
+        };
+    }
+
+    let mut seen = HashSet::new();
+    for name in names.iter_mut() {
+        let base = name.clone();
+        let mut counter = 1;
+        while !seen.insert(name.to_lowercase()) {
+            counter += 1;
+            *name = format!("{base}_{counter}");
+        }
+    }
+}
+
+/// `name`, or `name (1)`, `name (2)`... whichever has none of its files on
+/// disk, apart from those `reuse` accepts.
+fn free_name(dir: &Path, name: &str, extensions: &[&str], reuse: impl Fn(&Path) -> bool) -> String {
+    let taken = |candidate: &str| {
+        extensions.iter().any(|extension| {
+            let path = dir.join(format!("{candidate}.{extension}"));
+            path.exists() && !reuse(&path)
+        })
+    };
+    let mut candidate = name.to_owned();
+    let mut counter = 0;
+    while taken(&candidate) {
+        counter += 1;
+        candidate = format!("{name} ({counter})");
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::echo360::captions::CaptionFormat;
+
+    fn lesson(id: &str) -> Video {
+        Video {
+            lesson: LessonData {
+                id: id.to_owned(),
+                display_name: format!("Lesson {id}"),
+            },
+            has_content: true,
+            ..Default::default()
+        }
+    }
+
+    /// Stands in for a finished download of `path`.
+    fn downloaded(video: &Video, source: Source, path: &Path, contents: &str) -> Outcome {
+        fs::write(path, contents).unwrap();
+        Outcome {
+            job: Job {
+                lesson_id: video.lesson.id.clone(),
+                media_id: "media".to_owned(),
+                url: String::new(),
+                source,
+                path: path.to_owned(),
+                compare_size: false,
+            },
+            result: Ok(contents.len() as u64),
+        }
+    }
+
+    #[test]
+    fn keeps_names_across_downloads() {
+        let dir = std::env::temp_dir().join(format!("echo360-names-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let options = DownloadOptions {
+            path: dir.clone(),
+            ..Default::default()
+        };
+        let section = Section::default();
+        let video = lesson("1");
+        let add = |library: &mut Library, outcome| {
+            let report = Report {
+                outcomes: vec![outcome],
+            };
+            library
+                .add(&dir, &section, &[&video], &options.template, &report)
+                .unwrap();
+        };
+        let name = |library: &Library, video: &Video, extensions: &[&str]| {
+            output_name(
+                &options,
+                library,
+                &dir,
+                "COMP1234".to_owned(),
+                video,
+                extensions,
+            )
+        };
+        let mut library = Library::default();
+
+        // The video first, then its captions
+        assert_eq!(name(&library, &video, &["mp4"]), "COMP1234");
+        let mp4 = dir.join("COMP1234.mp4");
+        add(
+            &mut library,
+            downloaded(&video, Source::File, &mp4, "video"),
+        );
+        assert_eq!(name(&library, &video, &["mp4", "vtt"]), "COMP1234");
+        let captions = Source::Captions(vec![CaptionFormat::Vtt]);
+        let vtt = dir.join("COMP1234.vtt");
+        add(&mut library, downloaded(&video, captions, &vtt, "WEBVTT\n"));
+
+        // Both stay recorded, so the next download finds them in place
+        let files: Vec<&Path> = library.records[0]
+            .files
+            .iter()
+            .map(|file| file.path.as_path())
+            .collect();
+        assert_eq!(
+            files,
+            vec![Path::new("COMP1234.mp4"), Path::new("COMP1234.vtt")]
+        );
+        assert_eq!(name(&library, &video, &["mp4", "vtt"]), "COMP1234");
+
+        // Another lesson's files are never taken over
+        assert_eq!(name(&library, &lesson("2"), &["mp4"]), "COMP1234 (1)");
+        let rename = DownloadOptions {
+            existing: ExistingFiles::Rename,
+            ..options.clone()
+        };
+        assert_eq!(
+            output_name(
+                &rename,
+                &library,
+                &dir,
+                "COMP1234".to_owned(),
+                &video,
+                &["mp4"]
+            ),
+            "COMP1234 (1)"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn finds_the_hls_playlist() {
+        let properties: PlayerProperties = serde_json::from_str(
+            r#"{"data": {"playableAudioVideo": {"playableMedias": [
+                {"uri": "https://content.echo360.org/a/s1q1.mp4", "isHls": false},
+                {"uri": "https://content.echo360.org/z/s1_av.m3u8", "isHls": true},
+                {"uri": "https://content.echo360.org/a/s2_av.m3u8", "isHls": true}
+            ]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            properties.playlist().unwrap().as_str(),
+            "https://content.echo360.org/z/s1_av.m3u8"
+        );
+
+        let without: PlayerProperties = serde_json::from_str(r#"{"data": {}}"#).unwrap();
+        assert_eq!(without.playlist(), None);
     }
 }
@@ -1,27 +1,37 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
+use clap::Parser;
 use eframe::egui;
-use thirtyfour::prelude::*;
 
 mod app;
+mod cli;
 mod echo360;
-// mod task;
+mod task;
 
 use app::App;
+use cli::Cli;
 
-#[derive(Debug)]
-pub enum Error {
-    LoginFail(WebDriverError),
-}
+fn main() {
+    // Any arguments mean the command line, which needs somewhere to print
+    #[cfg(all(windows, not(debug_assertions)))]
+    if std::env::args_os().len() > 1 {
+        attach_console();
+    }
+    env_logger::init();
 
-impl From<WebDriverError> for Error {
-    fn from(err: WebDriverError) -> Self {
-        Self::LoginFail(err)
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        if let Err(err) = cli::run(command) {
+            eprintln!("error: {err}");
+            std::process::exit(1);
+        }
+        return;
     }
-}
 
-fn main() {
-    env_logger::init();
+    // Background tasks for the GUI run on this runtime
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let guard = runtime.enter();
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default(),
         ..Default::default()
@@ -38,4 +48,24 @@ fn main() {
         }),
     )
     .unwrap();
+
+    // Don't wait on blocking tasks still running, e.g. an abandoned login
+    drop(guard);
+    runtime.shutdown_background();
+}
+
+/// Release builds on Windows have no console of their own, so the command line
+/// writes to the one it was started from.
+#[cfg(all(windows, not(debug_assertions)))]
+fn attach_console() {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn AttachConsole(process_id: u32) -> i32;
+    }
+    const ATTACH_PARENT_PROCESS: u32 = u32::MAX;
+
+    // Fails when started without a console, e.g. from Explorer
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
 }
@@ -6,6 +6,9 @@
 //!
 //! These are backed by a tokio threadpool impl.
 
+// Kept as a complete utility, not every helper is used by the app
+#![allow(dead_code)]
+
 use std::fmt::Debug;
 
 use tokio::sync::oneshot;
@@ -13,12 +16,21 @@ use tokio::sync::oneshot::error::TryRecvError;
 
 /// A task is a way of performing long running tasks without blocking the egui thread,
 /// in a way that is ergonomic for egui usage.
-#[derive(Default)]
 pub struct Task<T> {
     recv: Option<oneshot::Receiver<T>>,
     value: Option<T>,
 }
 
+// Not derived, as that would require `T: Default`
+impl<T> Default for Task<T> {
+    fn default() -> Self {
+        Self {
+            recv: None,
+            value: None,
+        }
+    }
+}
+
 impl<T: Debug> Debug for Task<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Task")
@@ -119,9 +131,8 @@ where
                 }
             },
             None => {
-                if self.value.is_some() {
-                    // is_some -> unwrap as a borrowck hack
-                    TaskState::Ok(self.value.as_ref().unwrap())
+                if let Some(value) = &self.value {
+                    TaskState::Ok(value)
                 } else {
                     TaskState::NotFired
                 }
@@ -131,6 +142,12 @@ where
         Ok(v)
     }
 
+    /// Take the finished value out of this task, leaving it ready to fire again.
+    pub fn take(&mut self) -> Option<T> {
+        let _ = self.try_state();
+        self.value.take()
+    }
+
     /// Get the state of this task. Panics if the background task fails.
     pub fn state(&mut self) -> TaskState<&T> {
         self.try_state().expect("background task panicked")